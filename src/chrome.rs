@@ -0,0 +1,478 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::flow::FlowEvent;
+use crate::parser::{Diagnostic, Run};
+use crate::resources::ResourceEvent;
+use crate::trace::Trace;
+
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+/// An entry in the Chrome trace event format.
+///
+/// Each variant serializes as its own event object, so a list of these
+/// serializes as the JSON array format.
+#[derive(PartialEq, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Event {
+    Complete(Trace),
+    Flow(FlowEvent),
+    Resource(ResourceEvent),
+    Metadata(Metadata),
+}
+
+/// Names a process or a thread in the trace viewer.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Metadata {
+    ProcessName { pid: u32, name: String },
+    ThreadName { pid: u32, tid: u32, name: String },
+}
+
+impl Serialize for Metadata {
+    //  Metadata events from the tracing format,
+    //  https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU/preview#heading=h.xqopa5m0e28f,
+    //
+    //      {
+    //         "name": "process_name" | "thread_name",
+    //         "ph": "M",
+    //         "pid": int,
+    //         "tid": int,
+    //         "args": {"name": "string"}
+    //     }
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (kind, pid, tid, name) = match self {
+            Metadata::ProcessName { pid, name } => ("process_name", pid, None, name),
+            Metadata::ThreadName { pid, tid, name } => ("thread_name", pid, Some(tid), name),
+        };
+        let length = if tid.is_some() { 5 } else { 4 };
+        let mut map = serializer.serialize_map(Some(length))?;
+        map.serialize_entry("name", kind)?;
+        map.serialize_entry("ph", "M")?;
+        map.serialize_entry("pid", pid)?;
+        if let Some(tid) = tid {
+            map.serialize_entry("tid", tid)?;
+        }
+        let mut args = BTreeMap::new();
+        args.insert("name", name);
+        map.serialize_entry("args", &args)?;
+        map.end()
+    }
+}
+
+//  The JSON object format of the tracing format,
+//  https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU/preview#heading=h.f2f0yd51wi15,
+//  with any diagnostics in the "otherData" metadata
+#[derive(Serialize)]
+struct ObjectFormat<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: &'a [Event],
+    #[serde(rename = "otherData")]
+    other_data: OtherData<'a>,
+}
+
+#[derive(Serialize)]
+struct OtherData<'a> {
+    diagnostics: &'a [Diagnostic],
+}
+
+/// Writes `events` as minified JSON to `writer`.
+///
+/// The JSON array format is used, unless there are `diagnostics`, in which
+/// case the JSON object format is used so the diagnostics can be included in
+/// the trace's metadata.
+///
+/// # Errors
+///
+/// Returns [`serde_json::Error`](serde_json::Error) when `writer` fails.
+pub fn write_json<W: Write>(
+    writer: W,
+    events: &[Event],
+    diagnostics: &[Diagnostic],
+) -> Result<(), serde_json::Error> {
+    if diagnostics.is_empty() {
+        serde_json::to_writer(writer, events)
+    } else {
+        let object = ObjectFormat {
+            trace_events: events,
+            other_data: OtherData { diagnostics },
+        };
+        serde_json::to_writer(writer, &object)
+    }
+}
+
+//  Either form of the tracing format, when reading.  The diagnostics are
+//  those `write_json()` puts in the "otherData"
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TraceFile {
+    Array(Vec<Value>),
+    Object {
+        #[serde(rename = "traceEvents")]
+        trace_events: Vec<Value>,
+        #[serde(rename = "otherData", default)]
+        other_data: ReadOtherData,
+    },
+}
+
+#[derive(Deserialize, Default)]
+struct ReadOtherData {
+    #[serde(default)]
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Whether `content`, the start of some input, looks like a Chrome trace
+/// instead of ctest output.
+///
+/// An object, or an array of objects, is a trace.  Other output starting with
+/// `[`, like the `[ctest]` prefix of CMake Tools, isn't.
+pub fn is_trace_json(content: &[u8]) -> bool {
+    let mut start = content.iter().filter(|c| !c.is_ascii_whitespace());
+    match start.next() {
+        Some(b'{') => true,
+        Some(b'[') => matches!(start.next(), Some(b'{') | Some(b']') | None),
+        _ => false,
+    }
+}
+
+// Adds the `trace` of one thread of a test using `processors` threads to the
+// trace of its other threads, if they've been seen
+fn add_thread(traces: &mut Vec<Trace>, trace: Trace, processors: usize) {
+    if processors > 1 {
+        let other = traces.iter_mut().rev().find(|t| {
+            t.name == trace.name
+                && t.start == trace.start
+                && t.duration == trace.duration
+                && t.processors() < processors
+        });
+        if let Some(other) = other {
+            other.extra_threads.push(trace.thread_number);
+            return;
+        }
+    }
+    traces.push(trace);
+}
+
+/// Reads a trace in the Chrome trace event format, as written by
+/// [`write_json()`](write_json), back into the tests of a [`Run`](Run).
+///
+/// Both the JSON array and the JSON object formats are read, as is the
+/// streaming form of the array without its closing `]`.  Tests are read from
+/// complete, `"X"`, events and from matching begin, `"B"`, and end, `"E"`,
+/// events on the same thread.  Other events, and the resource tracks, are
+/// skipped.  The traces are in the order the tests finished.
+///
+/// # Errors
+///
+/// Returns [`serde_json::Error`](serde_json::Error) when `reader` fails or
+/// does not contain a Chrome trace.
+pub fn read_json<R: Read>(mut reader: R) -> Result<Run, serde_json::Error> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .map_err(serde_json::Error::io)?;
+    let trimmed = content.trim();
+    let file: TraceFile = if trimmed.starts_with('[') && !trimmed.ends_with(']') {
+        let events = trimmed.trim_end_matches(|c: char| c == ',' || c.is_whitespace());
+        serde_json::from_str(&format!("{}]", events))?
+    } else {
+        serde_json::from_str(trimmed)?
+    };
+    let (events, diagnostics) = match file {
+        TraceFile::Array(events) => (events, vec![]),
+        TraceFile::Object {
+            trace_events,
+            other_data,
+        } => (trace_events, other_data.diagnostics),
+    };
+
+    let mut traces = vec![];
+    // The begin events waiting on their end, for each process and thread
+    let mut begun: HashMap<(u64, u64), Vec<Value>> = HashMap::new();
+    for event in events {
+        if event["cat"] == "resource" {
+            continue;
+        }
+        let thread = (
+            event["pid"].as_u64().unwrap_or(0),
+            event["tid"].as_u64().unwrap_or(0),
+        );
+        match event["ph"].as_str() {
+            Some("X") => {
+                let processors = event["args"]["processors"].as_u64().unwrap_or(1);
+                let trace = serde_json::from_value(event)?;
+                add_thread(&mut traces, trace, processors as usize);
+            }
+            Some("B") => begun.entry(thread).or_default().push(event),
+            Some("E") => {
+                if let Some(begin) = begun.get_mut(&thread).and_then(|b| b.pop()) {
+                    let start = begin["ts"].as_f64().unwrap_or(0.0);
+                    let end = event["ts"].as_f64().unwrap_or(start);
+                    let args = match &event["args"]["status"] {
+                        Value::Null => &begin["args"],
+                        _ => &event["args"],
+                    };
+                    let complete = json!({
+                        "name": begin["name"],
+                        "ph": "X",
+                        "ts": start,
+                        "dur": end - start,
+                        "tid": thread.1,
+                        "args": args,
+                    });
+                    traces.push(serde_json::from_value(complete)?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    traces.sort_by_key(|t: &Trace| t.start + t.duration);
+    Ok(Run {
+        traces,
+        diagnostics,
+        ..Default::default()
+    })
+}
+
+/// Writes events as they happen, using the streaming form of the JSON array
+/// format.
+///
+/// Each event is flushed as soon as it's written.  The trace viewers accept
+/// the array without its closing `]`, so the output can be opened at any
+/// point, before [`finish()`](StreamWriter::finish) is called.
+pub struct StreamWriter<W: Write> {
+    writer: W,
+    empty: bool,
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Starts the array of events in `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`serde_json::Error`](serde_json::Error) when `writer` fails.
+    pub fn new(mut writer: W) -> Result<StreamWriter<W>, serde_json::Error> {
+        writer.write_all(b"[").map_err(serde_json::Error::io)?;
+        writer.flush().map_err(serde_json::Error::io)?;
+        Ok(StreamWriter {
+            writer,
+            empty: true,
+        })
+    }
+
+    /// Appends `event` to the array.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`serde_json::Error`](serde_json::Error) when `writer` fails.
+    pub fn write_event(&mut self, event: &Event) -> Result<(), serde_json::Error> {
+        if !self.empty {
+            self.writer.write_all(b",").map_err(serde_json::Error::io)?;
+        }
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.empty = false;
+        self.writer.flush().map_err(serde_json::Error::io)
+    }
+
+    /// Closes the array of events, returning the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`serde_json::Error`](serde_json::Error) when `writer` fails.
+    pub fn finish(mut self) -> Result<W, serde_json::Error> {
+        self.writer.write_all(b"]").map_err(serde_json::Error::io)?;
+        self.writer.flush().map_err(serde_json::Error::io)?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn events() -> Vec<Event> {
+        vec![Event::Complete(Trace {
+            name: "foo".into(),
+            duration: Duration::from_millis(1),
+            ..Default::default()
+        })]
+    }
+
+    #[test]
+    fn test_write_array_format() {
+        let mut json = vec![];
+        write_json(&mut json, &events(), &[]).unwrap();
+        let expected =
+            r#"[{"name":"foo","cat":"test","ph":"X","ts":0,"dur":1000,"pid":0,"tid":0}]"#;
+        assert_eq!(String::from_utf8(json).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_object_format_with_diagnostics() {
+        let diagnostic = Diagnostic {
+            line: 3,
+            text: "oops".into(),
+            message: "bad line".into(),
+        };
+        let mut json = vec![];
+        write_json(&mut json, &events(), &[diagnostic]).unwrap();
+        let expected = r#"{"traceEvents":[{"name":"foo","cat":"test","ph":"X","ts":0,"dur":1000,"pid":0,"tid":0}],"otherData":{"diagnostics":[{"line":3,"text":"oops","message":"bad line"}]}}"#;
+        assert_eq!(String::from_utf8(json).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_stream_events() {
+        let mut stream = StreamWriter::new(vec![]).unwrap();
+        assert_eq!(stream.writer, b"[");
+        for event in events().iter().chain(events().iter()) {
+            stream.write_event(event).unwrap();
+        }
+        let json = stream.finish().unwrap();
+        let event = r#"{"name":"foo","cat":"test","ph":"X","ts":0,"dur":1000,"pid":0,"tid":0}"#;
+        let expected = format!("[{},{}]", event, event);
+        assert_eq!(String::from_utf8(json).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_stream_no_events() {
+        let stream = StreamWriter::new(vec![]).unwrap();
+        assert_eq!(stream.finish().unwrap(), b"[]");
+    }
+
+    #[test]
+    fn test_is_trace_json() {
+        assert!(is_trace_json(b"  [{\"name\":"));
+        assert!(is_trace_json(b"[\n  {"));
+        assert!(is_trace_json(b"[]"));
+        assert!(is_trace_json(b"{\"traceEvents\":"));
+        assert!(!is_trace_json(b"[ctest]     Start 1: a_test"));
+        assert!(!is_trace_json(b"Test project /tmp/build"));
+        assert!(!is_trace_json(b""));
+    }
+
+    #[test]
+    fn test_read_array_format() {
+        let mut json = vec![];
+        write_json(&mut json, &events(), &[]).unwrap();
+        let run = read_json(json.as_slice()).unwrap();
+        assert_eq!(
+            run.traces,
+            vec![Trace::new(
+                "foo",
+                Duration::new(0, 0),
+                Duration::from_millis(1)
+            )]
+        );
+        assert_eq!(run.diagnostics, vec![]);
+    }
+
+    #[test]
+    fn test_read_object_format_with_diagnostics() {
+        let diagnostic = Diagnostic {
+            line: 3,
+            text: "oops".into(),
+            message: "bad line".into(),
+        };
+        let mut json = vec![];
+        write_json(&mut json, &events(), std::slice::from_ref(&diagnostic)).unwrap();
+        let run = read_json(json.as_slice()).unwrap();
+        assert_eq!(run.traces.len(), 1);
+        assert_eq!(run.diagnostics, vec![diagnostic]);
+    }
+
+    #[test]
+    fn test_read_streaming_format() {
+        let json = r#"[{"name":"foo","ph":"X","ts":0,"dur":1000,"pid":0,"tid":0},
+            "#;
+        let run = read_json(json.as_bytes()).unwrap();
+        assert_eq!(run.traces.len(), 1);
+        assert_eq!(read_json("[".as_bytes()).unwrap().traces, vec![]);
+    }
+
+    #[test]
+    fn test_read_begin_and_end_events() {
+        let json = r#"[
+            {"name":"outer","ph":"B","ts":0,"pid":0,"tid":1},
+            {"name":"inner","ph":"B","ts":10,"pid":0,"tid":1},
+            {"ph":"E","ts":20,"pid":0,"tid":1},
+            {"ph":"E","ts":50,"pid":0,"tid":1,"args":{"status":"Timeout"}},
+            {"ph":"E","ts":60,"pid":0,"tid":2}
+        ]"#;
+        let run = read_json(json.as_bytes()).unwrap();
+        let inner = Trace::new(
+            "inner",
+            Duration::from_micros(10),
+            Duration::from_micros(10),
+        )
+        .with_thread_number(1);
+        let outer = Trace::new("outer", Duration::new(0, 0), Duration::from_micros(50))
+            .with_thread_number(1)
+            .with_status(crate::trace::TestStatus::Timeout);
+        assert_eq!(run.traces, vec![inner, outer]);
+    }
+
+    #[test]
+    fn test_read_merges_processor_threads() {
+        let big = Trace {
+            name: "big".into(),
+            duration: Duration::from_millis(5),
+            thread_number: 2,
+            extra_threads: vec![0, 1],
+            ..Default::default()
+        };
+        let events: Vec<Event> = big.per_thread().into_iter().map(Event::Complete).collect();
+        let mut json = vec![];
+        write_json(&mut json, &events, &[]).unwrap();
+        assert_eq!(read_json(json.as_slice()).unwrap().traces, vec![big]);
+    }
+
+    #[test]
+    fn test_read_skips_other_events() {
+        let json = r#"[
+            {"name":"foo","cat":"test","ph":"X","ts":0,"dur":1000,"pid":0,"tid":0},
+            {"name":"db","cat":"dependency","ph":"s","id":1,"ts":1,"pid":0,"tid":0},
+            {"name":"foo","cat":"resource","ph":"X","ts":0,"dur":1000,"pid":1,"tid":0},
+            {"name":"thread_name","ph":"M","pid":1,"tid":0,"args":{"name":"gpus 0 slot 0"}}
+        ]"#;
+        let run = read_json(json.as_bytes()).unwrap();
+        assert_eq!(run.traces.len(), 1);
+    }
+
+    #[test]
+    fn test_read_invalid_json() {
+        assert!(read_json("Test project /tmp/build".as_bytes()).is_err());
+        assert!(read_json(r#"{"not":"a trace"}"#.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_serialize_thread_name() {
+        let event = Event::Metadata(Metadata::ThreadName {
+            pid: 1,
+            tid: 2,
+            name: "gpus 0 slot 0".into(),
+        });
+        let expected =
+            r#"{"name":"thread_name","ph":"M","pid":1,"tid":2,"args":{"name":"gpus 0 slot 0"}}"#;
+        assert_eq!(serde_json::to_string(&event).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_serialize_process_name() {
+        let event = Event::Metadata(Metadata::ProcessName {
+            pid: 1,
+            name: "resources".into(),
+        });
+        let expected = r#"{"name":"process_name","ph":"M","pid":1,"args":{"name":"resources"}}"#;
+        assert_eq!(serde_json::to_string(&event).unwrap(), expected);
+    }
+}
@@ -0,0 +1,248 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use flate2::read::MultiGzDecoder;
+use ruzstd::decoding::errors::{FrameDecoderError, ReadFrameHeaderError};
+use ruzstd::decoding::{BlockDecodingStrategy, FrameDecoder};
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+
+/// The compression formats which can be read.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// Determines the compression from the magic bytes at the start of the
+    /// content.
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if magic.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if magic.starts_with(XZ_MAGIC) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+}
+
+fn invalid_data<E: std::fmt::Display>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+// Decodes each frame of a zstd stream in turn, as concatenated `.zst` files
+// and `pzstd` output have several, skipping any skippable frames
+struct ZstdFrames<R: BufRead> {
+    source: R,
+    frame: FrameDecoder,
+}
+
+impl<R: BufRead> ZstdFrames<R> {
+    fn new(source: R) -> io::Result<Self> {
+        let mut frames = ZstdFrames {
+            source,
+            frame: FrameDecoder::new(),
+        };
+        frames.next_frame()?;
+        Ok(frames)
+    }
+
+    // Starts decoding the next frame, false when there are no more
+    fn next_frame(&mut self) -> io::Result<bool> {
+        loop {
+            if self.source.fill_buf()?.is_empty() {
+                return Ok(false);
+            }
+            match self.frame.init(&mut self.source) {
+                Ok(()) => return Ok(true),
+                Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                    length,
+                    ..
+                })) => {
+                    let mut skipped = (&mut self.source).take(length.into());
+                    io::copy(&mut skipped, &mut io::sink())?;
+                }
+                Err(error) => return Err(invalid_data(error)),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for ZstdFrames<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // Blocks are decoded until there's enough to fill `buf`, as in
+            // `ruzstd::decoding::StreamingDecoder`
+            while self.frame.can_collect() < buf.len() && !self.frame.is_finished() {
+                let needed = buf.len() - self.frame.can_collect();
+                self.frame
+                    .decode_blocks(&mut self.source, BlockDecodingStrategy::UptoBytes(needed))
+                    .map_err(invalid_data)?;
+            }
+            let read = self.frame.read(buf)?;
+            if read > 0 || buf.is_empty() || !self.next_frame()? {
+                return Ok(read);
+            }
+        }
+    }
+}
+
+/// Wraps `reader` so that its content is decompressed, if it's compressed.
+///
+/// The compression is detected from the magic bytes at the start of the
+/// content, not a file extension, so it works for stdin as well.
+///
+/// xz content is decompressed into memory up front, as `lzma-rs` can only
+/// write what it decompresses, so it needs as much memory as the
+/// uncompressed output and can't be read as it's written.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `reader` fails, or when the start of
+/// a zstd or xz stream is invalid.
+pub fn decompress<'a, R: Read + 'a>(mut reader: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut magic = vec![0; XZ_MAGIC.len()];
+    let mut length = 0;
+    // Pipes may provide less than asked for on each read
+    while length < magic.len() {
+        let read = reader.read(&mut magic[length..])?;
+        if read == 0 {
+            break;
+        }
+        length += read;
+    }
+    magic.truncate(length);
+
+    let compression = Compression::detect(&magic);
+    let reader = Cursor::new(magic).chain(reader);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(ZstdFrames::new(BufReader::new(reader))?),
+        Compression::Xz => {
+            // lzma-rs only decompresses into a writer, not as a reader
+            let mut decompressed = vec![];
+            lzma_rs::xz_decompress(&mut BufReader::new(reader), &mut decompressed)
+                .map_err(invalid_data)?;
+            Box::new(Cursor::new(decompressed))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    const CONTENT: &str = "    Start  1: test_one\n";
+
+    fn read_all(reader: &[u8]) -> String {
+        let mut content = String::new();
+        decompress(reader)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+        assert_eq!(
+            Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::detect(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]),
+            Compression::Xz
+        );
+        assert_eq!(Compression::detect(b"Test project"), Compression::None);
+        assert_eq!(Compression::detect(&[0x1f]), Compression::None);
+    }
+
+    #[test]
+    fn test_uncompressed() {
+        assert_eq!(read_all(CONTENT.as_bytes()), CONTENT);
+    }
+
+    #[test]
+    fn test_short_uncompressed() {
+        assert_eq!(read_all(b"ab"), "ab");
+        assert_eq!(read_all(b""), "");
+    }
+
+    #[test]
+    fn test_gzip() {
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(CONTENT.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(read_all(&compressed), CONTENT);
+    }
+
+    #[test]
+    fn test_zstd() {
+        let compressed = ruzstd::encoding::compress_to_vec(
+            CONTENT.as_bytes(),
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+
+        assert_eq!(read_all(&compressed), CONTENT);
+    }
+
+    #[test]
+    fn test_zstd_frames() {
+        let mut compressed = vec![];
+        for content in CONTENT.split_inclusive(' ') {
+            compressed.extend(ruzstd::encoding::compress_to_vec(
+                content.as_bytes(),
+                ruzstd::encoding::CompressionLevel::Fastest,
+            ));
+        }
+        // A skippable frame, i.e. the index `pzstd` writes
+        compressed.extend_from_slice(&[0x50, 0x2a, 0x4d, 0x18, 0x02, 0x00, 0x00, 0x00, 0xab, 0xcd]);
+        compressed.extend(ruzstd::encoding::compress_to_vec(
+            "1/1 Test #1".as_bytes(),
+            ruzstd::encoding::CompressionLevel::Fastest,
+        ));
+
+        assert_eq!(read_all(&compressed), format!("{}1/1 Test #1", CONTENT));
+    }
+
+    #[test]
+    fn test_invalid_zstd() {
+        let mut invalid = ZSTD_MAGIC.to_vec();
+        invalid.extend_from_slice(b"not really zstd");
+
+        assert!(decompress(invalid.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_xz() {
+        let mut compressed = vec![];
+        lzma_rs::xz_compress(&mut CONTENT.as_bytes(), &mut compressed).unwrap();
+
+        assert_eq!(read_all(&compressed), CONTENT);
+    }
+
+    #[test]
+    fn test_invalid_xz() {
+        let mut invalid = XZ_MAGIC.to_vec();
+        invalid.extend_from_slice(b"not really xz");
+
+        assert!(decompress(invalid.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use std::collections::HashMap;
+use std::io;
+use std::io::BufRead;
+use std::time::Duration;
+
+/// The historical durations of tests, which ctest keeps in
+/// `Testing/Temporary/CTestCostData.txt` of the build directory.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CostData {
+    average: HashMap<String, Duration>,
+}
+
+//  The cost data is a line for each test, with the number of runs and the
+//  average duration in seconds, then the tests which failed last run:
+//
+//      test_one 12 0.25
+//      test_two 3 10.5
+//      ---
+//      test_two
+//
+//  Test names may contain spaces, so the numbers are taken from the end.
+fn parse_cost_line(line: &str) -> Option<(&str, Duration)> {
+    let mut fields = line.trim_end().rsplitn(3, ' ');
+    let seconds: f64 = fields.next()?.parse().ok()?;
+    let _runs: u64 = fields.next()?.parse().ok()?;
+    let name = fields.next()?;
+    if name.is_empty() {
+        return None;
+    }
+    // Negative, infinite, and too large durations are invalid
+    Some((name, Duration::try_from_secs_f64(seconds).ok()?))
+}
+
+impl CostData {
+    /// Reads a `CTestCostData.txt` file.
+    ///
+    /// Lines which aren't understood are skipped, as ctest itself does.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::Error`](io::Error) when there is a failure to read the
+    /// lines of the provided `reader`.
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<CostData> {
+        let mut average = HashMap::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line == "---" {
+                break;
+            }
+            if let Some((name, duration)) = parse_cost_line(&line) {
+                average.insert(name.to_string(), duration);
+            }
+        }
+        Ok(CostData { average })
+    }
+
+    /// The average duration of the test `name` over its previous runs, if
+    /// it has run before.
+    pub fn average(&self, name: &str) -> Option<Duration> {
+        self.average.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COST_DATA: &str =
+        "test_one 12 0.25\ntest with spaces 3 10.5\nbroken line\n---\ntest_one\n";
+
+    #[test]
+    fn test_parse_cost_line() {
+        assert_eq!(
+            parse_cost_line("test_one 12 0.25"),
+            Some(("test_one", Duration::from_millis(250)))
+        );
+        assert_eq!(parse_cost_line("test_one 0.25"), None);
+        assert_eq!(parse_cost_line("test_one twelve 0.25"), None);
+        assert_eq!(parse_cost_line(" 12 0.25"), None);
+        assert_eq!(parse_cost_line("test_one 12 -0.25"), None);
+        assert_eq!(parse_cost_line("test_one 12 inf"), None);
+        assert_eq!(parse_cost_line("test_one 12 1e30"), None);
+    }
+
+    #[test]
+    fn test_read_cost_data() {
+        let data = CostData::from_reader(COST_DATA.as_bytes()).unwrap();
+        assert_eq!(data.average("test_one"), Some(Duration::from_millis(250)));
+        assert_eq!(
+            data.average("test with spaces"),
+            Some(Duration::from_millis(10500))
+        );
+        assert_eq!(data.average("broken line"), None);
+        assert_eq!(data.average("not_a_test"), None);
+    }
+}
@@ -0,0 +1,583 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::cost_data::CostData;
+use crate::parser::{ParseEvent, Summary};
+use crate::test_info::TestInfo;
+use crate::trace::TestStatus;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use crossterm::{event, execute, queue, terminal};
+use std::collections::HashSet;
+use std::io;
+use std::io::Write;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How often the dashboard is redrawn while waiting on the ctest output.
+pub const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+// Width of the test name column
+const NAME_WIDTH: usize = 40;
+
+// A test running in a lane, timed from when its start was seen
+struct RunningTest {
+    name: String,
+    seen: Instant,
+}
+
+// A test which finished, with how long ctest said it took
+struct FinishedTest {
+    name: String,
+    status: TestStatus,
+    duration: Duration,
+}
+
+/// How a line of the dashboard should stand out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    None,
+    /// A heading
+    Title,
+    /// A test running longer than it usually does
+    Slow,
+    /// A test which failed, or is about to time out
+    Failed,
+}
+
+/// A line of the dashboard, already fit to the width of the terminal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub text: String,
+    pub highlight: Highlight,
+}
+
+/// The tests currently running in each of ctest's job lanes, and those which
+/// have finished.
+///
+/// The elapsed time of a running test is compared to its average from
+/// previous runs, in ctest's cost data, and its `TIMEOUT` property.
+pub struct Dashboard {
+    cost_data: CostData,
+    test_info: TestInfo,
+    lanes: Vec<Option<RunningTest>>,
+    finished: Vec<FinishedTest>,
+    started: Instant,
+    summary: Option<Summary>,
+}
+
+// Formats a duration compactly, i.e. "4.20s", "3m05s", or "1h02m".
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{:.2}s", duration.as_secs_f64())
+    } else if seconds < 60 * 60 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}h{:02}m", seconds / (60 * 60), seconds / 60 % 60)
+    }
+}
+
+// Shortens `text` to at most `width` characters.
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+impl Dashboard {
+    /// Creates a dashboard for a run starting at `now`, using `cost_data`
+    /// and the `TIMEOUT` properties in `test_info` for how long tests are
+    /// expected to take.
+    pub fn new(cost_data: CostData, test_info: TestInfo, now: Instant) -> Dashboard {
+        Dashboard {
+            cost_data,
+            test_info,
+            lanes: vec![],
+            finished: vec![],
+            started: now,
+            summary: None,
+        }
+    }
+
+    /// Updates the dashboard for `event`, which was seen at `now`.
+    pub fn update(&mut self, event: &ParseEvent, now: Instant) {
+        match event {
+            ParseEvent::TestStarted {
+                name,
+                thread_number,
+                extra_threads,
+                ..
+            } => {
+                // A test using more than one processor holds all its lanes
+                for lane in std::iter::once(thread_number).chain(extra_threads) {
+                    let lane = *lane as usize;
+                    if self.lanes.len() <= lane {
+                        self.lanes.resize_with(lane + 1, || None);
+                    }
+                    self.lanes[lane] = Some(RunningTest {
+                        name: name.clone(),
+                        seen: now,
+                    });
+                }
+            }
+            ParseEvent::TestFinished(trace) => {
+                for lane in &mut self.lanes {
+                    if lane.as_ref().map(|t| &t.name) == Some(&trace.name) {
+                        *lane = None;
+                    }
+                }
+                self.finished.push(FinishedTest {
+                    name: trace.name.clone(),
+                    status: trace.status.clone(),
+                    duration: trace.duration,
+                });
+            }
+            ParseEvent::Summary(summary) => self.summary = Some(summary.clone()),
+        }
+    }
+
+    /// The dashboard as of `now`, fit to `width` columns and at most
+    /// `height` lines.
+    ///
+    /// The lanes come first, then as many of the most recently finished
+    /// tests as fit.
+    pub fn render(&self, width: usize, height: usize, now: Instant) -> Vec<Line> {
+        let line = |text: String, highlight| Line {
+            text: truncate(&text, width),
+            highlight,
+        };
+
+        let running = self
+            .lanes
+            .iter()
+            .flatten()
+            .map(|t| &t.name)
+            .collect::<HashSet<_>>()
+            .len();
+        let failed = self
+            .finished
+            .iter()
+            .filter(|t| t.status.is_failure())
+            .count();
+        let state = match &self.summary {
+            Some(summary) => format!("done in {}", format_duration(summary.total_time)),
+            None => format!(
+                "elapsed {}",
+                format_duration(now.saturating_duration_since(self.started))
+            ),
+        };
+        let mut lines = vec![
+            line(
+                format!(
+                    "ctest_tracing  {} running  {} finished  {} failed  {}",
+                    running,
+                    self.finished.len(),
+                    failed,
+                    state
+                ),
+                Highlight::Title,
+            ),
+            line(String::new(), Highlight::None),
+            line(
+                format!(
+                    "{:>4}  {:<name$}  {:>8}  {:>8}  {:>8}",
+                    "lane",
+                    "running",
+                    "elapsed",
+                    "average",
+                    "timeout",
+                    name = NAME_WIDTH
+                ),
+                Highlight::Title,
+            ),
+        ];
+
+        for (number, lane) in self.lanes.iter().enumerate() {
+            let (text, highlight) = match lane {
+                Some(test) => self.running_line(number, test, now),
+                None => (format!("{:>4}  -", number), Highlight::None),
+            };
+            lines.push(line(text, highlight));
+        }
+
+        lines.push(line(String::new(), Highlight::None));
+        lines.push(line("finished".into(), Highlight::Title));
+        let room = height.saturating_sub(lines.len());
+        let skip = self.finished.len().saturating_sub(room);
+        for test in &self.finished[skip..] {
+            let highlight = if test.status.is_failure() {
+                Highlight::Failed
+            } else {
+                Highlight::None
+            };
+            let text = format!(
+                "{:>8}  {:<name$}  {}",
+                format_duration(test.duration),
+                test.name,
+                test.status,
+                name = NAME_WIDTH
+            );
+            lines.push(line(text, highlight));
+        }
+
+        lines.truncate(height);
+        lines
+    }
+
+    fn running_line(&self, lane: usize, test: &RunningTest, now: Instant) -> (String, Highlight) {
+        let elapsed = now.saturating_duration_since(test.seen);
+        let average = self.cost_data.average(&test.name);
+        let timeout = self.test_info.timeout(&test.name);
+        let optional = |d: Option<Duration>| d.map(format_duration).unwrap_or_else(|| "-".into());
+
+        // Nearing the timeout matters more than being slower than usual
+        let (note, highlight) = if timeout.is_some_and(|t| elapsed >= t.mul_f64(0.9)) {
+            ("near timeout", Highlight::Failed)
+        } else if average.is_some_and(|a| elapsed > a) {
+            ("slow", Highlight::Slow)
+        } else {
+            ("", Highlight::None)
+        };
+        let text = format!(
+            "{:>4}  {:<name$}  {:>8}  {:>8}  {:>8}  {}",
+            lane,
+            test.name,
+            format_duration(elapsed),
+            optional(average),
+            optional(timeout),
+            note,
+            name = NAME_WIDTH
+        );
+        (text.trim_end().to_string(), highlight)
+    }
+}
+
+// Puts the terminal into full screen mode, restoring it when dropped, even
+// when panicking
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        enable_raw_mode()?;
+        let screen = Screen;
+        execute!(io::stderr(), EnterAlternateScreen, Hide)?;
+        Ok(screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), Show, LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+fn draw(dashboard: &Dashboard) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    let mut stderr = io::stderr();
+    let lines = dashboard.render(width as usize, height as usize, Instant::now());
+    for (row, line) in lines.iter().enumerate() {
+        queue!(stderr, MoveTo(0, row as u16), Clear(ClearType::CurrentLine))?;
+        let color = match line.highlight {
+            Highlight::None => None,
+            Highlight::Title => Some(Color::Cyan),
+            Highlight::Slow => Some(Color::Yellow),
+            Highlight::Failed => Some(Color::Red),
+        };
+        match color {
+            Some(color) => queue!(
+                stderr,
+                SetForegroundColor(color),
+                Print(&line.text),
+                ResetColor
+            )?,
+            None => queue!(stderr, Print(&line.text))?,
+        }
+    }
+    queue!(
+        stderr,
+        MoveTo(0, lines.len() as u16),
+        Clear(ClearType::FromCursorDown)
+    )?;
+    stderr.flush()
+}
+
+// Handles any pending key presses, returning false when the dashboard should
+// be closed
+fn handle_keys() -> io::Result<bool> {
+    while event::poll(Duration::from_secs(0))? {
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event::read()?
+        {
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                // Raw mode swallows the interrupt, so act on it here
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    let _ = execute!(io::stderr(), Show, LeaveAlternateScreen);
+                    let _ = disable_raw_mode();
+                    std::process::exit(130);
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn run(mut dashboard: Dashboard, receiver: Receiver<ParseEvent>) -> io::Result<()> {
+    let _screen = Screen::enter()?;
+    loop {
+        match receiver.recv_timeout(REFRESH_INTERVAL) {
+            Ok(event) => {
+                dashboard.update(&event, Instant::now());
+                // Catch up on everything already seen before drawing
+                for event in receiver.try_iter() {
+                    dashboard.update(&event, Instant::now());
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        draw(&dashboard)?;
+        if !handle_keys()? {
+            return Ok(());
+        }
+    }
+}
+
+/// A [`Dashboard`](Dashboard) shown full screen in the terminal, from its
+/// own thread so it keeps updating while waiting on the ctest output.
+///
+/// The dashboard is drawn on stderr, leaving stdout for the trace.  It's
+/// closed when this is finished or dropped, or by pressing `q`.
+pub struct TerminalDashboard {
+    sender: Option<Sender<ParseEvent>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl TerminalDashboard {
+    /// Shows `dashboard` in the terminal.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::Error`](io::Error) when stderr is not a terminal.
+    pub fn start(dashboard: Dashboard) -> io::Result<TerminalDashboard> {
+        use std::io::IsTerminal;
+        if !io::stderr().is_terminal() {
+            return Err(io::Error::other(
+                "The dashboard requires stderr to be a terminal",
+            ));
+        }
+        let (sender, receiver) = channel();
+        let thread = thread::spawn(move || run(dashboard, receiver));
+        Ok(TerminalDashboard {
+            sender: Some(sender),
+            thread: Some(thread),
+        })
+    }
+
+    /// Passes `event` on to the dashboard.  Does nothing once the dashboard
+    /// has been closed.
+    pub fn update(&self, event: &ParseEvent) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(event.clone());
+        }
+    }
+
+    /// Closes the dashboard, restoring the terminal.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::Error`](io::Error) when drawing the dashboard failed.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.close()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.sender = None;
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("dashboard panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TerminalDashboard {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_info::TestProperties;
+    use crate::trace::Trace;
+
+    fn started(name: &str, thread_number: u32) -> ParseEvent {
+        ParseEvent::TestStarted {
+            name: name.into(),
+            number: 1,
+            start: Duration::new(0, 0),
+            thread_number,
+            extra_threads: vec![],
+        }
+    }
+
+    fn finished(name: &str, millis: u64, status: TestStatus) -> ParseEvent {
+        ParseEvent::TestFinished(
+            Trace::new(name, Duration::new(0, 0), Duration::from_millis(millis))
+                .with_status(status),
+        )
+    }
+
+    fn texts(lines: &[Line]) -> Vec<&str> {
+        lines.iter().map(|l| l.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(4200)), "4.20s");
+        assert_eq!(format_duration(Duration::from_secs(185)), "3m05s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h02m");
+    }
+
+    #[test]
+    fn test_lanes_and_finished_tests() {
+        let start = Instant::now();
+        let mut dashboard = Dashboard::new(CostData::default(), TestInfo::default(), start);
+        dashboard.update(&started("one", 0), start);
+        dashboard.update(&started("two", 1), start);
+        dashboard.update(&finished("one", 1500, TestStatus::Passed), start);
+
+        let lines = dashboard.render(200, 20, start + Duration::from_secs(2));
+        let name = |n: &str| format!("{:<width$}", n, width = NAME_WIDTH);
+        assert_eq!(
+            texts(&lines),
+            vec![
+                "ctest_tracing  1 running  1 finished  0 failed  elapsed 2.00s".to_string(),
+                "".into(),
+                format!("lane  {}   elapsed   average   timeout", name("running")),
+                "   0  -".into(),
+                format!("   1  {}     2.00s         -         -", name("two")),
+                "".into(),
+                "finished".into(),
+                format!("   1.50s  {}  Passed", name("one")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiple_processors_hold_lanes() {
+        let start = Instant::now();
+        let mut dashboard = Dashboard::new(CostData::default(), TestInfo::default(), start);
+        dashboard.update(
+            &ParseEvent::TestStarted {
+                name: "big".into(),
+                number: 1,
+                start: Duration::new(0, 0),
+                thread_number: 0,
+                extra_threads: vec![2],
+            },
+            start,
+        );
+        dashboard.update(&started("small", 1), start);
+
+        let lines = dashboard.render(200, 20, start);
+        let name = |n: &str| format!("{:<width$}", n, width = NAME_WIDTH);
+        assert_eq!(
+            texts(&lines[..6]),
+            vec![
+                "ctest_tracing  2 running  0 finished  0 failed  elapsed 0.00s".to_string(),
+                "".into(),
+                format!("lane  {}   elapsed   average   timeout", name("running")),
+                format!("   0  {}     0.00s         -         -", name("big")),
+                format!("   1  {}     0.00s         -         -", name("small")),
+                format!("   2  {}     0.00s         -         -", name("big")),
+            ]
+        );
+
+        dashboard.update(&finished("big", 10, TestStatus::Passed), start);
+        let lines = dashboard.render(200, 20, start);
+        assert_eq!(lines[3].text, "   0  -");
+        assert_eq!(lines[5].text, "   2  -");
+    }
+
+    #[test]
+    fn test_failed_tests_are_highlighted() {
+        let start = Instant::now();
+        let mut dashboard = Dashboard::new(CostData::default(), TestInfo::default(), start);
+        dashboard.update(&started("bad", 0), start);
+        dashboard.update(&finished("bad", 10, TestStatus::Timeout), start);
+
+        let lines = dashboard.render(200, 20, start);
+        assert!(lines[0].text.contains("1 failed"));
+        let last = lines.last().unwrap();
+        assert!(last.text.ends_with("Timeout"));
+        assert_eq!(last.highlight, Highlight::Failed);
+    }
+
+    #[test]
+    fn test_slow_and_near_timeout() {
+        let start = Instant::now();
+        let cost_data = CostData::from_reader("slow 3 1.0\nstuck 3 1.0\n".as_bytes()).unwrap();
+        let mut test_info = TestInfo::default();
+        test_info.insert(
+            "stuck",
+            TestProperties {
+                timeout: Some(Duration::from_secs(10)),
+                ..Default::default()
+            },
+        );
+        let mut dashboard = Dashboard::new(cost_data, test_info, start);
+        dashboard.update(&started("slow", 0), start + Duration::from_secs(7));
+        dashboard.update(&started("stuck", 1), start);
+
+        let lines = dashboard.render(200, 20, start + Duration::from_secs(9));
+        assert!(lines[3].text.ends_with("slow"));
+        assert_eq!(lines[3].highlight, Highlight::Slow);
+        assert!(lines[4].text.ends_with("10.00s  near timeout"));
+        assert_eq!(lines[4].highlight, Highlight::Failed);
+    }
+
+    #[test]
+    fn test_most_recent_finished_tests_fit() {
+        let start = Instant::now();
+        let mut dashboard = Dashboard::new(CostData::default(), TestInfo::default(), start);
+        for name in &["a", "b", "c", "d"] {
+            dashboard.update(&started(name, 0), start);
+            dashboard.update(&finished(name, 10, TestStatus::Passed), start);
+        }
+
+        let lines = dashboard.render(12, 8, start);
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[6].text, "   0.01s  c ");
+        assert_eq!(lines[7].text, "   0.01s  d ");
+    }
+
+    #[test]
+    fn test_done_after_summary() {
+        let start = Instant::now();
+        let mut dashboard = Dashboard::new(CostData::default(), TestInfo::default(), start);
+        let summary = Summary {
+            total: 0,
+            failed: 0,
+            total_time: Duration::from_secs(75),
+        };
+        dashboard.update(&ParseEvent::Summary(summary), start);
+
+        let lines = dashboard.render(200, 20, start);
+        assert!(lines[0].text.ends_with("done in 1m15s"));
+    }
+}
@@ -0,0 +1,381 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::trace::{TestStatus, Trace};
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+
+/// A test which both passed and failed over several runs.
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct FlakyTest {
+    pub name: String,
+    /// The number of times the test ran, over all the runs
+    pub runs: usize,
+    /// The number of times the test went from passing to failing, or failing
+    /// to passing, between one run of it and the next
+    pub flips: usize,
+    /// The average duration of the test
+    pub mean_duration: Duration,
+    /// The variance of the test's durations, in seconds squared
+    pub duration_variance: f64,
+    /// The indices of the runs the test failed in
+    pub failed_runs: Vec<usize>,
+}
+
+impl FlakyTest {
+    /// The fraction of the times the test ran after its first which had a
+    /// different outcome than the time before, from `0.0` to `1.0`.
+    pub fn flip_rate(&self) -> f64 {
+        if self.runs < 2 {
+            return 0.0;
+        }
+        self.flips as f64 / (self.runs - 1) as f64
+    }
+}
+
+// The outcomes of a test over the runs, in order
+#[derive(Default)]
+struct History {
+    outcomes: Vec<(usize, bool, Duration)>,
+}
+
+/// Finds the tests which flip between passing and failing over `runs`, i.e.
+/// the traces from a number of CI logs, oldest first.
+///
+/// A test which is skipped or not run in a run is ignored for that run, so
+/// only the runs where it actually ran are compared.  A test repeated in a
+/// run, like with `ctest --repeat until-pass`, counts each time it ran.
+///
+/// The runs are expected to be of the same set of tests, i.e. the same CI
+/// job.  A test missing from some of the runs, like one added or renamed
+/// part way through, is only compared over the runs it's in, so its flip rate
+/// is from fewer runs than the others.
+///
+/// The flaky tests are ordered by flip rate, highest first.
+pub fn find_flaky(runs: &[Vec<Trace>]) -> Vec<FlakyTest> {
+    let mut histories: BTreeMap<&str, History> = BTreeMap::new();
+    for (index, traces) in runs.iter().enumerate() {
+        for trace in traces {
+            if matches!(trace.status, TestStatus::Skipped | TestStatus::NotRun) {
+                continue;
+            }
+            histories.entry(&trace.name).or_default().outcomes.push((
+                index,
+                trace.status.is_failure(),
+                trace.duration,
+            ));
+        }
+    }
+
+    let mut flaky: Vec<FlakyTest> = histories
+        .into_iter()
+        .filter_map(|(name, history)| {
+            let outcomes = &history.outcomes;
+            let flips = outcomes.windows(2).filter(|w| w[0].1 != w[1].1).count();
+            if flips == 0 {
+                return None;
+            }
+            let mut failed_runs: Vec<usize> = outcomes
+                .iter()
+                .filter(|(_, failed, _)| *failed)
+                .map(|(run, _, _)| *run)
+                .collect();
+            failed_runs.dedup();
+
+            let seconds: Vec<f64> = outcomes.iter().map(|o| o.2.as_secs_f64()).collect();
+            let mean = seconds.iter().sum::<f64>() / seconds.len() as f64;
+            let variance =
+                seconds.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / seconds.len() as f64;
+
+            Some(FlakyTest {
+                name: name.to_string(),
+                runs: outcomes.len(),
+                flips,
+                mean_duration: Duration::from_secs_f64(mean),
+                duration_variance: variance,
+                failed_runs,
+            })
+        })
+        .collect();
+    flaky.sort_by(|a, b| b.flip_rate().total_cmp(&a.flip_rate()));
+    flaky
+}
+
+// A flaky test as written by `write_flaky_json()`
+#[derive(Serialize)]
+struct FlakyReport<'a> {
+    name: &'a str,
+    runs: usize,
+    flips: usize,
+    flip_rate: f64,
+    mean_seconds: f64,
+    duration_variance: f64,
+    failed_runs: Vec<&'a str>,
+}
+
+// The names of the runs `test` failed in
+fn failed_runs<'a>(test: &FlakyTest, run_names: &'a [String]) -> Vec<&'a str> {
+    test.failed_runs
+        .iter()
+        .map(|run| run_names[*run].as_str())
+        .collect()
+}
+
+/// Writes a report of the `flaky` tests, from [`find_flaky()`](find_flaky),
+/// for people to read.
+///
+/// The runs are named by `run_names`, i.e. the files they were read from, in
+/// the same order as the runs given to [`find_flaky()`](find_flaky).
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_flaky_text<W: Write>(
+    mut writer: W,
+    flaky: &[FlakyTest],
+    run_names: &[String],
+) -> io::Result<()> {
+    //  Each flaky test, with its statistics indented below it:
+    //
+    //      test_one
+    //          flip rate: 75.0% (3 of 4 runs after the first)
+    //          duration: mean 0.200 sec, variance 0.016000 sec²
+    //          failed in: run2.log, run5.log
+    if flaky.is_empty() {
+        writeln!(writer, "No flaky tests in {} runs", run_names.len())?;
+    }
+    for test in flaky {
+        writeln!(writer, "{}", test.name)?;
+        writeln!(
+            writer,
+            "    flip rate: {:.1}% ({} of {} runs after the first)",
+            test.flip_rate() * 100.0,
+            test.flips,
+            test.runs - 1
+        )?;
+        writeln!(
+            writer,
+            "    duration: mean {:.3} sec, variance {:.6} sec²",
+            test.mean_duration.as_secs_f64(),
+            test.duration_variance
+        )?;
+        writeln!(
+            writer,
+            "    failed in: {}",
+            failed_runs(test, run_names).join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the `flaky` tests, from [`find_flaky()`](find_flaky), as a JSON
+/// array on a single line.
+///
+/// The runs are named by `run_names`, as in
+/// [`write_flaky_text()`](write_flaky_text).
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_flaky_json<W: Write>(
+    mut writer: W,
+    flaky: &[FlakyTest],
+    run_names: &[String],
+) -> io::Result<()> {
+    //  An object for each flaky test:
+    //
+    //      [{"name":"test_one","runs":5,"flips":3,"flip_rate":0.75,
+    //        "mean_seconds":0.2,"duration_variance":0.016,
+    //        "failed_runs":["run2.log","run5.log"]}]
+    let report: Vec<FlakyReport> = flaky
+        .iter()
+        .map(|test| FlakyReport {
+            name: &test.name,
+            runs: test.runs,
+            flips: test.flips,
+            flip_rate: test.flip_rate(),
+            mean_seconds: test.mean_duration.as_secs_f64(),
+            duration_variance: test.duration_variance,
+            failed_runs: failed_runs(test, run_names),
+        })
+        .collect();
+    serde_json::to_writer(&mut writer, &report)?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed() -> TestStatus {
+        TestStatus::Failed(String::new())
+    }
+
+    #[test]
+    fn test_stable_tests_are_not_flaky() {
+        let runs = vec![
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(TestStatus::Passed),
+                Trace::new("b", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(failed()),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(TestStatus::Passed),
+                Trace::new("b", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(failed()),
+            ],
+        ];
+        assert_eq!(find_flaky(&runs), vec![]);
+    }
+
+    #[test]
+    fn test_flipping_test() {
+        let runs = vec![
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(TestStatus::Passed),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(300))
+                    .with_status(TestStatus::Timeout),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(TestStatus::Passed),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(TestStatus::Passed),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(400))
+                    .with_status(failed()),
+            ],
+        ];
+        let flaky = find_flaky(&runs);
+        assert_eq!(flaky.len(), 1);
+        let a = &flaky[0];
+        assert_eq!(a.name, "a");
+        assert_eq!(a.runs, 5);
+        assert_eq!(a.flips, 3);
+        assert_eq!(a.flip_rate(), 0.75);
+        assert_eq!(a.mean_duration, Duration::from_millis(200));
+        assert!((a.duration_variance - 0.016).abs() < 1e-9);
+        assert_eq!(a.failed_runs, vec![1, 4]);
+    }
+
+    #[test]
+    fn test_skipped_runs_are_ignored() {
+        let runs = vec![
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(failed()),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(0))
+                    .with_status(TestStatus::NotRun),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(0))
+                    .with_status(TestStatus::Skipped),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(failed()),
+            ],
+        ];
+        assert_eq!(find_flaky(&runs), vec![]);
+    }
+
+    #[test]
+    fn test_repeated_in_one_run() {
+        let runs = vec![vec![
+            Trace::new("a", Duration::new(0, 0), Duration::from_millis(100)).with_status(failed()),
+            Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                .with_status(TestStatus::Passed),
+        ]];
+        let flaky = find_flaky(&runs);
+        assert_eq!(flaky[0].flips, 1);
+        assert_eq!(flaky[0].failed_runs, vec![0]);
+    }
+
+    fn flaky_test() -> FlakyTest {
+        FlakyTest {
+            name: "a".into(),
+            runs: 5,
+            flips: 3,
+            mean_duration: Duration::from_millis(200),
+            duration_variance: 0.016,
+            failed_runs: vec![1, 4],
+        }
+    }
+
+    fn run_names() -> Vec<String> {
+        (1..=5).map(|run| format!("run{}.log", run)).collect()
+    }
+
+    #[test]
+    fn test_write_flaky_text() {
+        let mut output = vec![];
+        write_flaky_text(&mut output, &[flaky_test()], &run_names()).unwrap();
+        let expected = "a
+    flip rate: 75.0% (3 of 4 runs after the first)
+    duration: mean 0.200 sec, variance 0.016000 sec²
+    failed in: run2.log, run5.log
+";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_no_flaky_tests() {
+        let mut output = vec![];
+        write_flaky_text(&mut output, &[], &run_names()).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "No flaky tests in 5 runs\n"
+        );
+    }
+
+    #[test]
+    fn test_write_flaky_json() {
+        let mut output = vec![];
+        write_flaky_json(&mut output, &[flaky_test()], &run_names()).unwrap();
+        let expected = r#"[{"name":"a","runs":5,"flips":3,"flip_rate":0.75,"mean_seconds":0.2,"duration_variance":0.016,"failed_runs":["run2.log","run5.log"]}]
+"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_ordered_by_flip_rate() {
+        let runs = vec![
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(failed()),
+                Trace::new("b", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(TestStatus::Passed),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(TestStatus::Passed),
+                Trace::new("b", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(failed()),
+            ],
+            vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(TestStatus::Passed),
+                Trace::new("b", Duration::new(0, 0), Duration::from_millis(100))
+                    .with_status(TestStatus::Passed),
+            ],
+        ];
+        let names: Vec<String> = find_flaky(&runs).into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["b", "a"]);
+    }
+}
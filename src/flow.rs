@@ -0,0 +1,268 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::test_info::TestInfo;
+use crate::trace::Trace;
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Which end of a flow arrow an event represents.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum FlowPhase {
+    Start,
+    Finish,
+}
+
+/// One end of an arrow drawn between two tests.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FlowEvent {
+    pub name: String,
+    pub id: u64,
+    pub phase: FlowPhase,
+    pub timestamp: Duration,
+    pub thread_number: u32,
+}
+
+impl Serialize for FlowEvent {
+    //  Flow events from the tracing format,
+    //  https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU/preview#heading=h.4qqub5rv9ybk,
+    //  the start binds to the slice enclosing its timestamp and the finish,
+    //  with a binding point of "e", binds to the slice enclosing its
+    //  timestamp:
+    //
+    //      {
+    //         "name": "string",
+    //         "cat": "string",
+    //         "ph": "s" | "f",
+    //         "id": int,
+    //         "ts": int,
+    //         "pid": int,
+    //         "tid": int,
+    //         "bp": "e"
+    //     }
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let length = match self.phase {
+            FlowPhase::Start => 7,
+            FlowPhase::Finish => 8,
+        };
+        let mut map = serializer.serialize_map(Some(length))?;
+        map.serialize_entry("name", &self.name)?;
+        map.serialize_entry("cat", "dependency")?;
+        let phase = match self.phase {
+            FlowPhase::Start => "s",
+            FlowPhase::Finish => "f",
+        };
+        map.serialize_entry("ph", phase)?;
+        map.serialize_entry("id", &self.id)?;
+        let timestamp = self.timestamp.as_micros() as u64;
+        map.serialize_entry("ts", &timestamp)?;
+        map.serialize_entry("pid", &0)?;
+        map.serialize_entry("tid", &self.thread_number)?;
+        if self.phase == FlowPhase::Finish {
+            map.serialize_entry("bp", "e")?;
+        }
+        map.end()
+    }
+}
+
+/// Creates the flow events drawing an arrow from the end of each test's
+/// prerequisites to the start of the test.
+///
+/// Prerequisites which never ran, i.e. are not in `traces`, are ignored.
+pub fn flow_events(traces: &[Trace], info: &TestInfo) -> Vec<FlowEvent> {
+    let by_name: HashMap<&str, &Trace> = traces.iter().map(|t| (t.name.as_str(), t)).collect();
+    let mut events = vec![];
+    let mut id = 0;
+    for trace in traces {
+        for prerequisite in info.prerequisites(&trace.name) {
+            let before = match by_name.get(prerequisite.name.as_str()) {
+                Some(before) => before,
+                None => continue,
+            };
+
+            // The start of the flow needs to be inside of the prerequisite's
+            // slice in order to bind to it, so back off from the very end.
+            let mut end = before.start + before.duration;
+            if !before.duration.is_zero() {
+                end -= Duration::from_micros(1);
+            }
+
+            id += 1;
+            events.push(FlowEvent {
+                name: prerequisite.reason.clone(),
+                id,
+                phase: FlowPhase::Start,
+                timestamp: end,
+                thread_number: before.thread_number,
+            });
+            events.push(FlowEvent {
+                name: prerequisite.reason,
+                id,
+                phase: FlowPhase::Finish,
+                timestamp: trace.start,
+                thread_number: trace.thread_number,
+            });
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_info::TestProperties;
+    use serde_test::{assert_ser_tokens, Token};
+
+    fn trace(name: &str, start: u64, duration: u64, thread_number: u32) -> Trace {
+        Trace {
+            name: name.into(),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(duration),
+            thread_number,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_serialize_flow_start() {
+        let event = FlowEvent {
+            name: "db".into(),
+            id: 3,
+            phase: FlowPhase::Start,
+            timestamp: Duration::from_millis(5),
+            thread_number: 1,
+        };
+
+        assert_ser_tokens(
+            &event,
+            &[
+                Token::Map { len: Some(7) },
+                Token::String("name"),
+                Token::String("db"),
+                Token::String("cat"),
+                Token::String("dependency"),
+                Token::String("ph"),
+                Token::String("s"),
+                Token::String("id"),
+                Token::U64(3),
+                Token::String("ts"),
+                Token::U64(5000),
+                Token::String("pid"),
+                Token::I32(0),
+                Token::String("tid"),
+                Token::U32(1),
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_serialize_flow_finish() {
+        let event = FlowEvent {
+            name: "DEPENDS".into(),
+            id: 1,
+            phase: FlowPhase::Finish,
+            timestamp: Duration::from_millis(20),
+            thread_number: 4,
+        };
+
+        assert_ser_tokens(
+            &event,
+            &[
+                Token::Map { len: Some(8) },
+                Token::String("name"),
+                Token::String("DEPENDS"),
+                Token::String("cat"),
+                Token::String("dependency"),
+                Token::String("ph"),
+                Token::String("f"),
+                Token::String("id"),
+                Token::U64(1),
+                Token::String("ts"),
+                Token::U64(20000),
+                Token::String("pid"),
+                Token::I32(0),
+                Token::String("tid"),
+                Token::U32(4),
+                Token::String("bp"),
+                Token::String("e"),
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_flow_from_fixture_setup() {
+        let mut info = TestInfo::default();
+        let setup = TestProperties {
+            fixtures_setup: vec!["db".into()],
+            ..Default::default()
+        };
+        let user = TestProperties {
+            fixtures_required: vec!["db".into()],
+            ..Default::default()
+        };
+        info.insert("setup", setup);
+        info.insert("user", user);
+        let traces = vec![trace("setup", 0, 200, 0), trace("user", 200, 100, 1)];
+
+        assert_eq!(
+            flow_events(&traces, &info),
+            vec![
+                FlowEvent {
+                    name: "db".into(),
+                    id: 1,
+                    phase: FlowPhase::Start,
+                    timestamp: Duration::from_micros(199_999),
+                    thread_number: 0,
+                },
+                FlowEvent {
+                    name: "db".into(),
+                    id: 1,
+                    phase: FlowPhase::Finish,
+                    timestamp: Duration::from_millis(200),
+                    thread_number: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flow_ignores_prerequisites_not_run() {
+        let mut info = TestInfo::default();
+        let user = TestProperties {
+            depends: vec!["never_ran".into()],
+            ..Default::default()
+        };
+        info.insert("user", user);
+        let traces = vec![trace("user", 0, 100, 0)];
+
+        assert_eq!(flow_events(&traces, &info), vec![]);
+    }
+
+    #[test]
+    fn test_flow_ids_are_unique() {
+        let mut info = TestInfo::default();
+        let user = TestProperties {
+            depends: vec!["one".into(), "two".into()],
+            ..Default::default()
+        };
+        info.insert("user", user);
+        let traces = vec![
+            trace("one", 0, 100, 0),
+            trace("two", 0, 200, 1),
+            trace("user", 200, 100, 0),
+        ];
+
+        let ids: Vec<u64> = flow_events(&traces, &info).iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![1, 1, 2, 2]);
+    }
+}
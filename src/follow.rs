@@ -0,0 +1,238 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use std::fs;
+use std::fs::{File, Metadata};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Reads a file which is still being written, like `tail -F`.
+///
+/// Instead of reaching the end of the file, reads wait for more content to be
+/// written, or with an [idle timeout](Follower::set_idle_timeout) until it
+/// hasn't grown for that long.  Should the file be truncated, or replaced by
+/// a new file, after some of it was read, the read returns the end of the
+/// input once, with [`restarted()`](Follower::restarted) set, then reading
+/// starts over from the beginning of the new content.  The file doesn't need
+/// to exist yet.
+pub struct Follower {
+    path: PathBuf,
+    file: Option<File>,
+    identity: Option<(u64, u64)>,
+    position: u64,
+    poll_interval: Duration,
+    idle_timeout: Option<Duration>,
+    last_read: Instant,
+    restarted: bool,
+}
+
+// Identifies the file behind a path, to know when it's been replaced
+#[cfg(unix)]
+fn file_identity(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+impl Follower {
+    /// Creates a follower for `path`, checking for more content every
+    /// `poll_interval`.
+    pub fn new<P: AsRef<Path>>(path: P, poll_interval: Duration) -> Follower {
+        Follower {
+            path: path.as_ref().to_path_buf(),
+            file: None,
+            identity: None,
+            position: 0,
+            poll_interval,
+            idle_timeout: None,
+            last_read: Instant::now(),
+            restarted: false,
+        }
+    }
+
+    /// Ends the input once nothing has been written to the file for
+    /// `timeout`, or never when `None`, the default.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    /// Whether the last end of the input was because the file was truncated
+    /// or replaced, rather than the idle timeout passing.  Reads after this
+    /// continue from the start of the new content.
+    pub fn restarted(&self) -> bool {
+        self.restarted
+    }
+
+    // Opens the file if it's new, or rewinds it if it's been truncated.
+    // Returns true when there may be new content to read.
+    fn reopen_if_replaced(&mut self) -> io::Result<bool> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // Like `tail -F`, keep waiting for the file to (re)appear
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let identity = file_identity(&metadata);
+        if self.file.is_none() || identity != self.identity {
+            self.file = Some(File::open(&self.path)?);
+            self.identity = identity;
+            self.restarted = self.position > 0;
+            self.position = 0;
+            return Ok(true);
+        }
+
+        if metadata.len() < self.position {
+            if let Some(file) = self.file.as_mut() {
+                file.seek(SeekFrom::Start(0))?;
+            }
+            self.restarted = true;
+            self.position = 0;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+}
+
+impl Read for Follower {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.restarted = false;
+        loop {
+            if let Some(file) = self.file.as_mut() {
+                let read = file.read(buf)?;
+                if read > 0 {
+                    self.position += read as u64;
+                    self.last_read = Instant::now();
+                    return Ok(read);
+                }
+            }
+            if self.reopen_if_replaced()? {
+                if self.restarted {
+                    self.last_read = Instant::now();
+                    return Ok(0);
+                }
+                continue;
+            }
+            if let Some(timeout) = self.idle_timeout {
+                if self.last_read.elapsed() >= timeout {
+                    return Ok(0);
+                }
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use tempfile::tempdir;
+
+    const POLL: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn test_reads_existing_content() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        fs::write(&path, "line one\n").unwrap();
+
+        let mut reader = BufReader::new(Follower::new(&path, POLL));
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "line one\n");
+    }
+
+    #[test]
+    fn test_waits_for_content() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        let writer_path = path.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut file = File::create(&writer_path).unwrap();
+            file.write_all(b"first ").unwrap();
+            file.flush().unwrap();
+            thread::sleep(Duration::from_millis(50));
+            file.write_all(b"line\nsecond line\n").unwrap();
+        });
+
+        let mut reader = BufReader::new(Follower::new(&path, POLL));
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "first line\n");
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "second line\n");
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_starts_over_when_truncated() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        fs::write(&path, "a much longer first line\n").unwrap();
+
+        let mut reader = BufReader::new(Follower::new(&path, POLL));
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "a much longer first line\n");
+
+        fs::write(&path, "short\n").unwrap();
+        line.clear();
+        assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+        assert!(reader.get_ref().restarted());
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "short\n");
+        assert!(!reader.get_ref().restarted());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_starts_over_when_replaced() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        fs::write(&path, "old\n").unwrap();
+
+        let mut reader = BufReader::new(Follower::new(&path, POLL));
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "old\n");
+
+        let rotated = dir.path().join("new.txt");
+        fs::write(&rotated, "brand new content\n").unwrap();
+        fs::rename(&rotated, &path).unwrap();
+        line.clear();
+        assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+        assert!(reader.get_ref().restarted());
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "brand new content\n");
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        fs::write(&path, "only line\n").unwrap();
+
+        let mut follower = Follower::new(&path, POLL);
+        follower.set_idle_timeout(Some(Duration::from_millis(50)));
+        let mut content = String::new();
+        follower.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "only line\n");
+        assert!(!follower.restarted());
+    }
+}
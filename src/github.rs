@@ -0,0 +1,242 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::parser::Run;
+use crate::trace::{TestStatus, Trace};
+
+use std::cmp::Reverse;
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+
+// Escapes `text` for a cell of a Markdown table, which must stay on one line
+fn table_cell(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace(['\r', '\n'], " ")
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2} sec", duration.as_secs_f64())
+}
+
+/// Writes a Markdown summary of `run` for a GitHub Actions job summary, i.e.
+/// to append to `$GITHUB_STEP_SUMMARY`.
+///
+/// The summary has the number of tests which passed, failed, and were
+/// skipped, the wall clock time, the failed tests with their reasons, and
+/// the `slowest` longest tests.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_summary<W: Write>(mut writer: W, run: &Run, slowest: usize) -> io::Result<()> {
+    //  The summary:
+    //
+    //      ## ctest Results
+    //
+    //      | Passed | Failed | Skipped | Wall Time |
+    //      | ---: | ---: | ---: | ---: |
+    //      | 10 | 1 | 0 | 12.50 sec |
+    //
+    //      ### Failed Tests
+    //
+    //      | Test | Status | Duration |
+    //      | --- | --- | ---: |
+    //      | test_two | Timeout | 10.00 sec |
+    //
+    //      ### Slowest Tests
+    //
+    //      | Test | Duration |
+    //      | --- | ---: |
+    //      | test_two | 10.00 sec |
+    let count = |f: fn(&TestStatus) -> bool| run.traces.iter().filter(|t| f(&t.status)).count();
+    let passed = count(|s| *s == TestStatus::Passed);
+    let failed = count(TestStatus::is_failure);
+    let skipped = count(|s| matches!(s, TestStatus::Skipped | TestStatus::NotRun));
+
+    writeln!(writer, "## ctest Results")?;
+    writeln!(writer)?;
+    writeln!(writer, "| Passed | Failed | Skipped | Wall Time |")?;
+    writeln!(writer, "| ---: | ---: | ---: | ---: |")?;
+    writeln!(
+        writer,
+        "| {} | {} | {} | {} |",
+        passed,
+        failed,
+        skipped,
+        format_duration(run.wall_time())
+    )?;
+
+    let failures: Vec<&Trace> = run
+        .traces
+        .iter()
+        .filter(|t| t.status.is_failure())
+        .collect();
+    if !failures.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "### Failed Tests")?;
+        writeln!(writer)?;
+        writeln!(writer, "| Test | Status | Duration |")?;
+        writeln!(writer, "| --- | --- | ---: |")?;
+        for trace in failures {
+            writeln!(
+                writer,
+                "| {} | {} | {} |",
+                table_cell(&trace.name),
+                table_cell(&trace.status.to_string()),
+                format_duration(trace.duration)
+            )?;
+        }
+    }
+
+    let mut longest: Vec<&Trace> = run.traces.iter().collect();
+    longest.sort_by_key(|t| Reverse(t.duration));
+    longest.truncate(slowest);
+    if !longest.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "### Slowest Tests")?;
+        writeln!(writer)?;
+        writeln!(writer, "| Test | Duration |")?;
+        writeln!(writer, "| --- | ---: |")?;
+        for trace in longest {
+            writeln!(
+                writer,
+                "| {} | {} |",
+                table_cell(&trace.name),
+                format_duration(trace.duration)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Escapes the message of a workflow command
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+// Escapes a property, like the title, of a workflow command
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Writes an `::error` workflow command for each test in `traces` which
+/// failed, timed out, or crashed, so GitHub Actions shows it as an
+/// annotation.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_annotations<W: Write>(mut writer: W, traces: &[Trace]) -> io::Result<()> {
+    //  A workflow command for each failure:
+    //
+    //      ::error title=test_two::Timeout after 10.00 sec
+    for trace in traces.iter().filter(|t| t.status.is_failure()) {
+        writeln!(
+            writer,
+            "::error title={}::{}",
+            escape_property(&trace.name),
+            escape_data(&format!(
+                "{} after {}",
+                trace.status,
+                format_duration(trace.duration)
+            ))
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Summary;
+
+    fn run() -> Run {
+        Run {
+            traces: vec![
+                Trace::new("fast", Duration::new(0, 0), Duration::from_millis(10)),
+                Trace::new("slow", Duration::new(0, 0), Duration::from_millis(5000)),
+                Trace::new("a|b", Duration::new(0, 0), Duration::from_millis(300))
+                    .with_status(TestStatus::Failed("Required \"x\"".into())),
+                Trace::new("hangs", Duration::new(0, 0), Duration::from_millis(1500))
+                    .with_status(TestStatus::Timeout),
+                Trace::new("skips", Duration::new(0, 0), Duration::from_millis(0))
+                    .with_status(TestStatus::Skipped),
+            ],
+            summary: Some(Summary {
+                total: 5,
+                failed: 2,
+                total_time: Duration::from_millis(6820),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_write_summary() {
+        let mut output = vec![];
+        write_summary(&mut output, &run(), 2).unwrap();
+        let expected = r#"## ctest Results
+
+| Passed | Failed | Skipped | Wall Time |
+| ---: | ---: | ---: | ---: |
+| 2 | 2 | 1 | 6.82 sec |
+
+### Failed Tests
+
+| Test | Status | Duration |
+| --- | --- | ---: |
+| a\|b | Failed  Required "x" | 0.30 sec |
+| hangs | Timeout | 1.50 sec |
+
+### Slowest Tests
+
+| Test | Duration |
+| --- | ---: |
+| slow | 5.00 sec |
+| hangs | 1.50 sec |
+"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_summary_of_passing_run() {
+        let run = Run {
+            traces: vec![Trace::new(
+                "fast",
+                Duration::new(0, 0),
+                Duration::from_millis(10),
+            )],
+            ..Default::default()
+        };
+        let mut output = vec![];
+        write_summary(&mut output, &run, 0).unwrap();
+        let expected = "## ctest Results\n\n| Passed | Failed | Skipped | Wall Time |\n| ---: | ---: | ---: | ---: |\n| 1 | 0 | 0 | 0.01 sec |\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_annotations() {
+        let mut traces = run().traces;
+        traces.push(
+            Trace::new(
+                "crash: 1, 2",
+                Duration::new(0, 0),
+                Duration::from_millis(20),
+            )
+            .with_status(TestStatus::Exception("Other\n100%".into())),
+        );
+        let mut output = vec![];
+        write_annotations(&mut output, &traces).unwrap();
+        let expected = r#"::error title=a|b::Failed  Required "x" after 0.30 sec
+::error title=hangs::Timeout after 1.50 sec
+::error title=crash%3A 1%2C 2::Exception: Other%0A100%25 after 0.02 sec
+"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
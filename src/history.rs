@@ -0,0 +1,641 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::parser::Run;
+use crate::trace::TestStatus;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A test of a recorded run.
+#[derive(PartialEq, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RecordedTest {
+    pub name: String,
+    /// The duration of the test in seconds
+    pub seconds: f64,
+    /// How the test finished, as ctest reports it, i.e. "Passed"
+    pub status: String,
+}
+
+impl RecordedTest {
+    /// The duration of the test.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.seconds.max(0.0))
+    }
+
+    /// How the test finished.
+    pub fn status(&self) -> TestStatus {
+        TestStatus::from_result(&self.status)
+    }
+
+    // Whether the test actually ran, so its duration means something
+    fn ran(&self) -> bool {
+        !matches!(self.status(), TestStatus::Skipped | TestStatus::NotRun)
+    }
+}
+
+/// A run of the tests as kept in the history.
+#[derive(PartialEq, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RecordedRun {
+    /// When the run was recorded, in seconds since the Unix epoch
+    pub recorded_at: u64,
+    /// A name for the run, i.e. the commit or CI job it's from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// The file the run was read from
+    pub source: String,
+    /// The wall clock time of the whole run in seconds, when ctest
+    /// reported it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_seconds: Option<f64>,
+    /// The tests in the order they finished
+    pub tests: Vec<RecordedTest>,
+}
+
+impl RecordedRun {
+    /// The record of `run`, read from `source`, at `recorded_at`.
+    pub fn new(run: &Run, source: &str, label: Option<String>, recorded_at: SystemTime) -> Self {
+        let tests = run
+            .traces
+            .iter()
+            .map(|trace| RecordedTest {
+                name: trace.name.clone(),
+                seconds: trace.duration.as_secs_f64(),
+                status: trace.status.to_string(),
+            })
+            .collect();
+        RecordedRun {
+            recorded_at: recorded_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            label,
+            source: source.to_string(),
+            total_seconds: run.summary.as_ref().map(|s| s.total_time.as_secs_f64()),
+            tests,
+        }
+    }
+}
+
+//  The history is a JSON Lines file, with a line for each run appended as
+//  it's recorded, oldest first:
+//
+//      {"recorded_at":1634515200,"label":"abc123","source":"ctest.log","total_seconds":12.5,"tests":[{"name":"test_one","seconds":0.25,"status":"Passed"}]}
+//      {"recorded_at":1634601600,"source":"ctest.log","tests":[...]}
+
+/// Appends `run` to the history file at `path`, creating the file, and its
+/// parent directories, when needed.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when the file can't be written.
+pub fn append(path: &Path, run: &RecordedRun) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_vec(run)?;
+    line.push(b'\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    // A single write, so a concurrent reader never sees half a line
+    file.write_all(&line)
+}
+
+/// Reads the runs of a history file, oldest first.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when there is a failure to read the
+/// lines of `reader`, or a line isn't a recorded run, or has a test whose
+/// duration is out of range.
+pub fn read<R: BufRead>(reader: R) -> io::Result<Vec<RecordedRun>> {
+    let mut runs = vec![];
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let run: RecordedRun = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Line {} of the history isn't a recorded run: {}",
+                    index + 1,
+                    e
+                ),
+            )
+        })?;
+        // So `RecordedTest::duration()` can't fail on a hand edited line
+        if let Some(test) = run
+            .tests
+            .iter()
+            .find(|t| Duration::try_from_secs_f64(t.seconds.max(0.0)).is_err())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Line {} of the history has an invalid duration for {}: {}",
+                    index + 1,
+                    test.name,
+                    test.seconds
+                ),
+            ));
+        }
+        runs.push(run);
+    }
+    Ok(runs)
+}
+
+/// The times a test ran in the history.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TestRun<'a> {
+    /// The position of the run in the history, starting at 1
+    pub number: usize,
+    pub run: &'a RecordedRun,
+    pub test: &'a RecordedTest,
+}
+
+/// The times the test `name` ran in the last `last` runs of `runs`, oldest
+/// first.
+pub fn test_history<'a>(runs: &'a [RecordedRun], name: &str, last: usize) -> Vec<TestRun<'a>> {
+    let skip = runs.len().saturating_sub(last);
+    runs.iter()
+        .enumerate()
+        .skip(skip)
+        .flat_map(|(index, run)| {
+            run.tests
+                .iter()
+                .filter(move |test| test.name == name)
+                .map(move |test| TestRun {
+                    number: index + 1,
+                    run,
+                    test,
+                })
+        })
+        .collect()
+}
+
+/// How the duration of a test has been behaving over a number of runs.
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Trend {
+    pub name: String,
+    /// The number of times the test ran
+    pub runs: usize,
+    pub median: Duration,
+    /// The duration 90% of the runs were at or under
+    pub p90: Duration,
+    /// How much longer the test takes each run, as a fraction of its mean
+    /// duration, i.e. `0.05` is 5% slower each run and `-0.05` is 5%
+    /// faster.
+    ///
+    /// This is the slope of the least squares fit of the durations.
+    pub trend: f64,
+}
+
+impl Trend {
+    /// The trend of the test `name` from its durations, oldest first.
+    pub fn new(name: &str, durations: &[Duration]) -> Self {
+        let mut sorted = durations.to_vec();
+        sorted.sort();
+        let median = match sorted.len() {
+            0 => Duration::new(0, 0),
+            n if n % 2 == 1 => sorted[n / 2],
+            n => (sorted[n / 2 - 1] + sorted[n / 2]) / 2,
+        };
+        // The nearest rank method
+        let rank = (sorted.len() as f64 * 0.9).ceil() as usize;
+        let p90 = sorted
+            .get(rank.saturating_sub(1))
+            .copied()
+            .unwrap_or_default();
+
+        Trend {
+            name: name.to_string(),
+            runs: durations.len(),
+            median,
+            p90,
+            trend: relative_slope(durations),
+        }
+    }
+}
+
+// The slope of the least squares line through `durations`, relative to
+// their mean
+fn relative_slope(durations: &[Duration]) -> f64 {
+    let n = durations.len() as f64;
+    if durations.len() < 2 {
+        return 0.0;
+    }
+    let seconds: Vec<f64> = durations.iter().map(Duration::as_secs_f64).collect();
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = seconds.iter().sum::<f64>() / n;
+    if mean_y == 0.0 {
+        return 0.0;
+    }
+    let (covariance, variance) = seconds
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(c, v), (x, y)| {
+            let dx = x as f64 - mean_x;
+            (c + dx * (y - mean_y), v + dx * dx)
+        });
+    covariance / variance / mean_y
+}
+
+/// The trends of every test which ran in the last `last` runs of `runs`,
+/// ordered by trend, the tests getting slower the fastest first.
+pub fn trends(runs: &[RecordedRun], last: usize) -> Vec<Trend> {
+    let skip = runs.len().saturating_sub(last);
+    let mut durations: BTreeMap<&str, Vec<Duration>> = BTreeMap::new();
+    for run in runs.iter().skip(skip) {
+        for test in run.tests.iter().filter(|t| t.ran()) {
+            durations
+                .entry(&test.name)
+                .or_default()
+                .push(test.duration());
+        }
+    }
+    let mut trends: Vec<Trend> = durations
+        .into_iter()
+        .map(|(name, durations)| Trend::new(name, &durations))
+        .collect();
+    trends.sort_by(|a, b| b.trend.total_cmp(&a.trend));
+    trends
+}
+
+// The trend of the times a test ran, leaving out those it didn't actually run
+fn history_trend(name: &str, test_runs: &[TestRun]) -> Trend {
+    let durations: Vec<Duration> = test_runs
+        .iter()
+        .filter(|t| t.test.ran())
+        .map(|t| t.test.duration())
+        .collect();
+    Trend::new(name, &durations)
+}
+
+/// Writes each of the `test_runs` of the test `name`, from
+/// [`test_history()`](test_history), and its trend, for people to read.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_history_text<W: Write>(
+    mut writer: W,
+    name: &str,
+    test_runs: &[TestRun],
+) -> io::Result<()> {
+    //  A row for each time the test ran, then its trend:
+    //
+    //        Run     Duration  Status
+    //          1    1.000 sec  Passed    abc123
+    //      median 1.000 sec, p90 1.000 sec, trend +0.0% a run
+    writeln!(writer, "  Run     Duration  Status")?;
+    for test_run in test_runs {
+        writeln!(
+            writer,
+            "{:>5} {:>8.3} sec  {:<8}{}",
+            test_run.number,
+            test_run.test.seconds,
+            test_run.test.status,
+            test_run
+                .run
+                .label
+                .as_ref()
+                .map(|l| format!("  {}", l))
+                .unwrap_or_default()
+        )?;
+    }
+    let trend = history_trend(name, test_runs);
+    writeln!(
+        writer,
+        "median {:.3} sec, p90 {:.3} sec, trend {:+.1}% a run",
+        trend.median.as_secs_f64(),
+        trend.p90.as_secs_f64(),
+        trend.trend * 100.0
+    )
+}
+
+// A trend as written by `write_history_json()` and `write_trends_json()`
+#[derive(Serialize)]
+struct TrendReport<'a> {
+    name: &'a str,
+    runs: usize,
+    median_seconds: f64,
+    p90_seconds: f64,
+    trend: f64,
+}
+
+impl<'a> From<&'a Trend> for TrendReport<'a> {
+    fn from(trend: &'a Trend) -> Self {
+        TrendReport {
+            name: &trend.name,
+            runs: trend.runs,
+            median_seconds: trend.median.as_secs_f64(),
+            p90_seconds: trend.p90.as_secs_f64(),
+            trend: trend.trend,
+        }
+    }
+}
+
+// A time a test ran as written by `write_history_json()`
+#[derive(Serialize)]
+struct TestRunReport<'a> {
+    run: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+    seconds: f64,
+    status: &'a str,
+}
+
+// The report written by `write_history_json()`
+#[derive(Serialize)]
+struct HistoryReport<'a> {
+    trend: TrendReport<'a>,
+    runs: Vec<TestRunReport<'a>>,
+}
+
+/// Writes the `test_runs` of the test `name`, as
+/// [`write_history_text()`](write_history_text), as a JSON object on a single
+/// line.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_history_json<W: Write>(
+    mut writer: W,
+    name: &str,
+    test_runs: &[TestRun],
+) -> io::Result<()> {
+    //  The trend, then each time the test ran:
+    //
+    //      {"trend":{"name":"a","runs":1,"median_seconds":1.0,...},
+    //       "runs":[{"run":1,"label":"abc123","seconds":1.0,"status":"Passed"}]}
+    let trend = history_trend(name, test_runs);
+    let report = HistoryReport {
+        trend: TrendReport::from(&trend),
+        runs: test_runs
+            .iter()
+            .map(|t| TestRunReport {
+                run: t.number,
+                label: t.run.label.as_deref(),
+                seconds: t.test.seconds,
+                status: &t.test.status,
+            })
+            .collect(),
+    };
+    serde_json::to_writer(&mut writer, &report)?;
+    writeln!(writer)
+}
+
+/// Writes the `trends`, from [`trends()`](trends), as a table for people to
+/// read.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_trends_text<W: Write>(mut writer: W, trends: &[Trend]) -> io::Result<()> {
+    //  A row for each test:
+    //
+    //            Median          P90    Trend  Runs  Test
+    //         1.500 sec    2.000 sec   +66.7%     2  slower
+    writeln!(writer, "      Median          P90    Trend  Runs  Test")?;
+    for trend in trends {
+        writeln!(
+            writer,
+            "{:>8.3} sec {:>8.3} sec {:>+7.1}% {:>5}  {}",
+            trend.median.as_secs_f64(),
+            trend.p90.as_secs_f64(),
+            trend.trend * 100.0,
+            trend.runs,
+            trend.name
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the `trends`, from [`trends()`](trends), as a JSON array on a
+/// single line.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_trends_json<W: Write>(mut writer: W, trends: &[Trend]) -> io::Result<()> {
+    //  An object for each test:
+    //
+    //      [{"name":"slower","runs":2,"median_seconds":1.5,"p90_seconds":2.0,
+    //        "trend":0.667}]
+    let report: Vec<TrendReport> = trends.iter().map(TrendReport::from).collect();
+    serde_json::to_writer(&mut writer, &report)?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Summary;
+    use crate::trace::Trace;
+    use tempfile::tempdir;
+
+    fn recorded(tests: &[(&str, f64, &str)]) -> RecordedRun {
+        RecordedRun {
+            source: "ctest.log".into(),
+            tests: tests
+                .iter()
+                .map(|(name, seconds, status)| RecordedTest {
+                    name: name.to_string(),
+                    seconds: *seconds,
+                    status: status.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn secs(millis: &[u64]) -> Vec<Duration> {
+        millis.iter().map(|m| Duration::from_millis(*m)).collect()
+    }
+
+    #[test]
+    fn test_record_run() {
+        let run = Run {
+            traces: vec![
+                Trace::new("a", Duration::new(0, 0), Duration::from_millis(250)),
+                Trace::new("b", Duration::new(0, 0), Duration::from_millis(500))
+                    .with_status(TestStatus::Timeout),
+            ],
+            summary: Some(Summary {
+                total: 2,
+                failed: 1,
+                total_time: Duration::from_secs(1),
+            }),
+            ..Default::default()
+        };
+        let recorded = RecordedRun::new(
+            &run,
+            "ctest.log",
+            Some("abc123".into()),
+            UNIX_EPOCH + Duration::from_secs(1634515200),
+        );
+        assert_eq!(
+            serde_json::to_string(&recorded).unwrap(),
+            r#"{"recorded_at":1634515200,"label":"abc123","source":"ctest.log","total_seconds":1.0,"tests":[{"name":"a","seconds":0.25,"status":"Passed"},{"name":"b","seconds":0.5,"status":"Timeout"}]}"#
+        );
+        assert_eq!(recorded.tests[1].status(), TestStatus::Timeout);
+        assert_eq!(recorded.tests[1].duration(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history").join("runs.jsonl");
+        let first = recorded(&[("a", 1.0, "Passed")]);
+        let second = recorded(&[("a", 2.0, "Failed")]);
+        append(&path, &first).unwrap();
+        append(&path, &second).unwrap();
+
+        let runs = read(io::BufReader::new(fs::File::open(&path).unwrap())).unwrap();
+        assert_eq!(runs, vec![first, second]);
+    }
+
+    #[test]
+    fn test_read_invalid_line() {
+        let history = "{\"recorded_at\":0,\"source\":\"a\",\"tests\":[]}\n\nnot json\n";
+        let error = read(history.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error
+            .to_string()
+            .starts_with("Line 3 of the history isn't a recorded run: "));
+    }
+
+    #[test]
+    fn test_read_invalid_duration() {
+        let history = concat!(
+            "{\"recorded_at\":0,\"source\":\"a\",\"tests\":[]}\n",
+            "{\"recorded_at\":0,\"source\":\"a\",\"tests\":[",
+            "{\"name\":\"test_one\",\"seconds\":1e30,\"status\":\"Passed\"}]}\n"
+        );
+        let error = read(history.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "Line 2 of the history has an invalid duration for test_one: 1000000000000000000000000000000"
+        );
+    }
+
+    #[test]
+    fn test_test_history() {
+        let runs = vec![
+            recorded(&[("a", 1.0, "Passed")]),
+            recorded(&[("b", 1.0, "Passed")]),
+            recorded(&[("a", 2.0, "Passed"), ("a", 3.0, "Failed")]),
+        ];
+        let history = test_history(&runs, "a", 10);
+        let numbers: Vec<(usize, f64)> =
+            history.iter().map(|t| (t.number, t.test.seconds)).collect();
+        assert_eq!(numbers, vec![(1, 1.0), (3, 2.0), (3, 3.0)]);
+
+        let history = test_history(&runs, "a", 2);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].number, 3);
+    }
+
+    #[test]
+    fn test_trend_statistics() {
+        let trend = Trend::new("a", &secs(&[100, 300, 200, 400]));
+        assert_eq!(trend.runs, 4);
+        assert_eq!(trend.median, Duration::from_millis(250));
+        assert_eq!(trend.p90, Duration::from_millis(400));
+        // The fit is 0.08 sec a run, on a mean of 0.25 sec
+        assert!((trend.trend - 0.32).abs() < 1e-9);
+
+        let trend = Trend::new(
+            "a",
+            &secs(&[100, 200, 300, 400, 500, 600, 700, 800, 900, 1000]),
+        );
+        assert_eq!(trend.median, Duration::from_millis(550));
+        assert_eq!(trend.p90, Duration::from_millis(900));
+
+        let trend = Trend::new("a", &secs(&[100]));
+        assert_eq!(trend.median, Duration::from_millis(100));
+        assert_eq!(trend.trend, 0.0);
+    }
+
+    #[test]
+    fn test_trends() {
+        let runs = vec![
+            recorded(&[("old", 9.0, "Passed")]),
+            recorded(&[("faster", 2.0, "Passed"), ("slower", 1.0, "Passed")]),
+            recorded(&[("faster", 1.0, "Passed"), ("slower", 0.0, "Not Run")]),
+            recorded(&[("faster", 1.0, "Passed"), ("slower", 2.0, "Failed")]),
+        ];
+        let trends = trends(&runs, 3);
+        let names: Vec<(&str, usize)> = trends.iter().map(|t| (t.name.as_str(), t.runs)).collect();
+        assert_eq!(names, vec![("slower", 2), ("faster", 3)]);
+        assert!(trends[0].trend > 0.0);
+        assert!(trends[1].trend < 0.0);
+    }
+
+    fn history_runs() -> Vec<RecordedRun> {
+        let mut first = recorded(&[("a", 1.0, "Passed")]);
+        first.label = Some("abc123".into());
+        vec![
+            first,
+            recorded(&[("a", 0.0, "Not Run")]),
+            recorded(&[("a", 2.0, "Failed")]),
+        ]
+    }
+
+    #[test]
+    fn test_write_history_text() {
+        let runs = history_runs();
+        let mut output = vec![];
+        write_history_text(&mut output, "a", &test_history(&runs, "a", 10)).unwrap();
+        // The status is padded, so the labels line up
+        let expected = concat!(
+            "  Run     Duration  Status\n",
+            "    1    1.000 sec  Passed    abc123\n",
+            "    2    0.000 sec  Not Run \n",
+            "    3    2.000 sec  Failed  \n",
+            "median 1.500 sec, p90 2.000 sec, trend +66.7% a run\n"
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_history_json() {
+        let runs = history_runs();
+        let mut output = vec![];
+        write_history_json(&mut output, "a", &test_history(&runs, "a", 1)).unwrap();
+        let expected = r#"{"trend":{"name":"a","runs":1,"median_seconds":2.0,"p90_seconds":2.0,"trend":0.0},"runs":[{"run":3,"seconds":2.0,"status":"Failed"}]}
+"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_trends_text() {
+        let trends = vec![Trend::new("slower", &secs(&[1000, 2000]))];
+        let mut output = vec![];
+        write_trends_text(&mut output, &trends).unwrap();
+        let expected = "      Median          P90    Trend  Runs  Test
+   1.500 sec    2.000 sec   +66.7%     2  slower
+";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_trends_json() {
+        let trends = vec![Trend::new("slower", &secs(&[1000, 2000]))];
+        let mut output = vec![];
+        write_trends_json(&mut output, &trends).unwrap();
+        let expected = r#"[{"name":"slower","runs":2,"median_seconds":1.5,"p90_seconds":2.0,"trend":0.6666666666666666}]
+"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
@@ -0,0 +1,335 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::parser::Run;
+use crate::trace::{TestStatus, Trace};
+
+use std::io;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Escapes `text` for an XML attribute or element.  Control characters aren't
+// allowed in XML 1.0, even escaped, so they're dropped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Formats `time` as the UTC date and time without a time zone, as the JUnit
+// schema's timestamp, i.e. "2021-10-18T14:03:09"
+fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+    // Howard Hinnant's civil from days, for days since 1970-01-01
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+// The JUnit schema allows at most 3 decimal places of seconds
+fn format_seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+//  A test case, with an element for a test which didn't pass:
+//
+//      <testcase name="string" classname="string" time="float">
+//        <failure message="string" type="string"/>
+//        <error message="string" type="string"/>
+//        <skipped message="string"/>
+//      </testcase>
+fn write_test_case<W: Write>(writer: &mut W, trace: &Trace) -> io::Result<()> {
+    let name = escape(&trace.name);
+    write!(
+        writer,
+        "  <testcase name=\"{}\" classname=\"{}\" time=\"{}\"",
+        name,
+        name,
+        format_seconds(trace.duration)
+    )?;
+    let element = match &trace.status {
+        TestStatus::Passed => None,
+        TestStatus::Failed(reason) if reason.is_empty() => {
+            Some("<failure message=\"Failed\" type=\"Failed\"/>".to_string())
+        }
+        TestStatus::Failed(reason) => Some(format!(
+            "<failure message=\"{}\" type=\"Failed\"/>",
+            escape(reason)
+        )),
+        TestStatus::Timeout => Some("<failure message=\"Timeout\" type=\"Timeout\"/>".to_string()),
+        TestStatus::Exception(kind) => Some(format!(
+            "<error message=\"{}\" type=\"{}\"/>",
+            escape(&trace.status.to_string()),
+            escape(kind)
+        )),
+        status => Some(format!("<skipped message=\"{}\"/>", status)),
+    };
+    match element {
+        Some(element) => writeln!(writer, ">\n    {}\n  </testcase>", element),
+        None => writeln!(writer, "/>"),
+    }
+}
+
+/// Writes `run` as a JUnit XML test suite named `name` to `writer`.
+///
+/// This is the JUnit XML format of Jenkins and Maven Surefire, which most CI
+/// systems read.  Failed tests and timeouts are failures, exceptions are
+/// errors, and tests which were skipped or not run are skipped.  The suite's
+/// timestamp is when the first test started, which is only known when the
+/// ctest output has timestamps, otherwise it's left out.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_junit<W: Write>(mut writer: W, run: &Run, name: &str) -> io::Result<()> {
+    //  The test suite:
+    //
+    //      <?xml version="1.0" encoding="UTF-8"?>
+    //      <testsuite name="string" tests="int" failures="int" errors="int"
+    //                 skipped="int" time="float" timestamp="YYYY-MM-DDThh:mm:ss">
+    //        <testcase .../>
+    //      </testsuite>
+    let count = |f: fn(&TestStatus) -> bool| run.traces.iter().filter(|t| f(&t.status)).count();
+    let errors = count(|s| matches!(s, TestStatus::Exception(_)));
+    let failures = count(|s| matches!(s, TestStatus::Failed(_) | TestStatus::Timeout));
+    let skipped = count(|s| matches!(s, TestStatus::Skipped | TestStatus::NotRun));
+
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    write!(
+        writer,
+        "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{}\"",
+        escape(name),
+        run.traces.len(),
+        failures,
+        errors,
+        skipped,
+        format_seconds(run.wall_time())
+    )?;
+    if let Some(start_time) = run.start_time {
+        write!(writer, " timestamp=\"{}\"", format_timestamp(start_time))?;
+    }
+    writeln!(writer, ">")?;
+    for trace in &run.traces {
+        write_test_case(&mut writer, trace)?;
+    }
+    writeln!(writer, "</testsuite>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Summary;
+    use regex::Regex;
+    use std::process::{Command, Stdio};
+
+    fn run() -> Run {
+        Run {
+            traces: vec![
+                Trace::new("passes", Duration::new(0, 0), Duration::from_millis(200)),
+                Trace::new(
+                    "fails <badly>",
+                    Duration::new(0, 0),
+                    Duration::from_millis(300),
+                )
+                .with_status(TestStatus::Failed("Required \"x\"".into())),
+                Trace::new(
+                    "times_out",
+                    Duration::new(0, 0),
+                    Duration::from_millis(1500),
+                )
+                .with_status(TestStatus::Timeout),
+                Trace::new("crashes", Duration::new(0, 0), Duration::from_millis(10))
+                    .with_status(TestStatus::Exception("SegFault".into())),
+                Trace::new("skips", Duration::new(0, 0), Duration::new(0, 0))
+                    .with_status(TestStatus::Skipped),
+            ],
+            start_time: Some(UNIX_EPOCH + Duration::from_secs(1634565789)),
+            summary: Some(Summary {
+                total: 5,
+                failed: 3,
+                total_time: Duration::from_millis(2051),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn junit(run: &Run) -> String {
+        let mut output = vec![];
+        write_junit(&mut output, run, "ctest").unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    // Validates `xml` against the JUnit schema of Jenkins and Maven
+    // Surefire, tests/junit-10.xsd, with `xmllint` from libxml2
+    fn validate(xml: &str) {
+        let schema = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/junit-10.xsd");
+        let mut xmllint = Command::new("xmllint")
+            .args(["--noout", "--schema", schema, "-"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("xmllint, from libxml2, is needed to validate the JUnit XML");
+        xmllint
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(xml.as_bytes())
+            .unwrap();
+        let output = xmllint.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    // Checks what the schema can't, that the counts match the test cases,
+    // the timestamp format, and that each case has at most one result with
+    // the attributes JUnit readers show.
+    fn check_structure(xml: &str) {
+        let document = roxmltree::Document::parse(xml).unwrap();
+        let suite = document.root_element();
+        assert_eq!(suite.tag_name().name(), "testsuite");
+        assert!(suite.attribute("name").is_some());
+        let time = Regex::new(r"^(([0-9]{0,3},)*[0-9]{3}|[0-9]{0,3})*(\.[0-9]{0,3})?$").unwrap();
+        assert!(time.is_match(suite.attribute("time").unwrap()));
+        if let Some(timestamp) = suite.attribute("timestamp") {
+            let pattern = Regex::new(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}$").unwrap();
+            assert!(pattern.is_match(timestamp), "{}", timestamp);
+        }
+
+        let cases: Vec<_> = suite.children().filter(|n| n.is_element()).collect();
+        let count = |name: &str| {
+            cases
+                .iter()
+                .filter(|c| c.children().any(|e| e.tag_name().name() == name))
+                .count()
+                .to_string()
+        };
+        assert_eq!(suite.attribute("tests").unwrap(), cases.len().to_string());
+        assert_eq!(suite.attribute("failures").unwrap(), count("failure"));
+        assert_eq!(suite.attribute("errors").unwrap(), count("error"));
+        assert_eq!(suite.attribute("skipped").unwrap(), count("skipped"));
+        for case in cases {
+            assert_eq!(case.tag_name().name(), "testcase");
+            assert!(case.attribute("name").is_some());
+            assert!(time.is_match(case.attribute("time").unwrap()));
+            let results: Vec<_> = case.children().filter(|n| n.is_element()).collect();
+            assert!(results.len() <= 1);
+            for result in results {
+                match result.tag_name().name() {
+                    "failure" | "error" => {
+                        assert!(result.attribute("message").is_some());
+                        assert!(result.attribute("type").is_some());
+                    }
+                    "skipped" => assert!(result.attribute("message").is_some()),
+                    other => panic!("unexpected <{}>", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a<b>&\"c'"), "a&lt;b&gt;&amp;&quot;c&apos;");
+        assert_eq!(escape("bell\u{7}\ttab"), "bell\ttab");
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00");
+        let time = UNIX_EPOCH + Duration::from_secs(1634565789);
+        assert_eq!(format_timestamp(time), "2021-10-18T14:03:09");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951782400);
+        assert_eq!(format_timestamp(leap_day), "2000-02-29T00:00:00");
+    }
+
+    #[test]
+    fn test_write_junit() {
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="ctest" tests="5" failures="2" errors="1" skipped="1" time="2.051" timestamp="2021-10-18T14:03:09">
+  <testcase name="passes" classname="passes" time="0.200"/>
+  <testcase name="fails &lt;badly&gt;" classname="fails &lt;badly&gt;" time="0.300">
+    <failure message="Required &quot;x&quot;" type="Failed"/>
+  </testcase>
+  <testcase name="times_out" classname="times_out" time="1.500">
+    <failure message="Timeout" type="Timeout"/>
+  </testcase>
+  <testcase name="crashes" classname="crashes" time="0.010">
+    <error message="Exception: SegFault" type="SegFault"/>
+  </testcase>
+  <testcase name="skips" classname="skips" time="0.000">
+    <skipped message="Skipped"/>
+  </testcase>
+</testsuite>
+"#;
+        let xml = junit(&run());
+        assert_eq!(xml, expected);
+        validate(&xml);
+        check_structure(&xml);
+    }
+
+    #[test]
+    fn test_without_timestamps() {
+        let mut run = run();
+        run.start_time = None;
+        run.summary = None;
+        run.traces[1].start = Duration::from_millis(200);
+
+        let xml = junit(&run);
+        assert!(xml.contains("time=\"1.500\">\n"));
+        assert!(!xml.contains("timestamp"));
+        validate(&xml);
+        check_structure(&xml);
+    }
+
+    #[test]
+    fn test_empty_run() {
+        let xml = junit(&Run::default());
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuite name=\"ctest\" tests=\"0\" failures=\"0\" errors=\"0\" skipped=\"0\" time=\"0.000\">\n</testsuite>\n"
+        );
+        validate(&xml);
+        check_structure(&xml);
+    }
+}
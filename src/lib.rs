@@ -3,5 +3,24 @@
 //    (See accompanying file LICENSE or copy at
 //          https://www.boost.org/LICENSE_1_0.txt)
 
+pub mod chrome;
+pub mod compression;
+pub mod cost_data;
+pub mod dashboard;
+pub mod flaky;
+pub mod flow;
+pub mod follow;
+pub mod github;
+pub mod history;
+pub mod junit;
+pub mod normalize;
+pub mod otlp;
+pub mod outlier;
 pub mod parser;
-mod trace;
+pub mod resources;
+pub mod shard;
+pub mod table;
+pub mod tap;
+pub mod teamcity;
+pub mod test_info;
+pub mod trace;
@@ -4,13 +4,129 @@
 //          https://www.boost.org/LICENSE_1_0.txt)
 
 use clio::{Input, Output};
-use ctest_tracing::parser::parse;
+use ctest_tracing::chrome::{is_trace_json, read_json, write_json, Event, StreamWriter};
+use ctest_tracing::compression::{decompress, Compression};
+use ctest_tracing::cost_data::CostData;
+use ctest_tracing::dashboard::{Dashboard, TerminalDashboard};
+use ctest_tracing::flaky::{find_flaky, write_flaky_json, write_flaky_text};
+use ctest_tracing::flow::flow_events;
+use ctest_tracing::follow::Follower;
+use ctest_tracing::github::{write_annotations, write_summary};
+use ctest_tracing::history;
+use ctest_tracing::history::{
+    test_history, trends, write_history_json, write_history_text, write_trends_json,
+    write_trends_text, RecordedRun,
+};
+use ctest_tracing::junit::write_junit;
+use ctest_tracing::otlp::{export_request, send, write_otlp_json, Export, ExportRequest, TraceId};
+use ctest_tracing::outlier::{
+    write_outlier_warnings, write_outliers_json, write_outliers_text, Baseline, DEFAULT_THRESHOLD,
+};
+use ctest_tracing::parser::{
+    parse_with_options, Events, ParseError, ParseEvent, ParseOptions, Run,
+};
+use ctest_tracing::resources::{resource_events, ResourceSpec};
+use ctest_tracing::shard::plan;
+use ctest_tracing::table::{write_csv, write_jsonl, Column};
+use ctest_tracing::tap::{is_tap, read_tap, write_tap};
+use ctest_tracing::teamcity::write_service_messages;
+use ctest_tracing::test_info::TestInfo;
+use ctest_tracing::trace::Trace;
+use flate2::write::GzEncoder;
+use regex::Regex;
 use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::{BufReader, Write};
-use std::path::Path;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant, SystemTime};
 use structopt::StructOpt;
 
+// How often `--follow` checks the input file for more output
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Where ctest keeps the durations of previous runs, relative to the build
+// directory
+const DEFAULT_COST_DATA: &str = "Testing/Temporary/CTestCostData.txt";
+
+// The timing history used by `record`, `history`, and `trends` when no
+// `--store` is given
+const DEFAULT_HISTORY: &str = "ctest_tracing_history.jsonl";
+
+// The number of the most recent runs of `--baseline-history` to use
+const BASELINE_HISTORY_RUNS: usize = 20;
+
+// The `--outlier-threshold` default, as text for the help
+static DEFAULT_OUTLIER_THRESHOLD: LazyLock<String> =
+    LazyLock::new(|| DEFAULT_THRESHOLD.to_string());
+
+// The `--columns` which can be selected, in the order they're written
+static COLUMN_NAMES: LazyLock<Vec<&str>> =
+    LazyLock::new(|| Column::ALL.iter().map(|c| c.name()).collect());
+
+// The name of the test suite written by `--format junit`
+const JUNIT_SUITE_NAME: &str = "ctest";
+
+// How much of the input is looked at to detect its format, enough for the
+// `TAP version` line after any leading blank lines
+const FORMAT_DETECTION_BYTES: u64 = 256;
+
+// The formats which can be written, see `--format`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Chrome,
+    Junit,
+    Github,
+    Teamcity,
+    Tap,
+    Csv,
+    Jsonl,
+    Otlp,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "chrome" => Ok(OutputFormat::Chrome),
+            "junit" => Ok(OutputFormat::Junit),
+            "github" => Ok(OutputFormat::Github),
+            "teamcity" => Ok(OutputFormat::Teamcity),
+            "tap" => Ok(OutputFormat::Tap),
+            "csv" => Ok(OutputFormat::Csv),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "otlp" => Ok(OutputFormat::Otlp),
+            _ => Err(format!("Unknown output format \"{}\"", value)),
+        }
+    }
+}
+
+// The formats which can be read, see `--from`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InputFormat {
+    Auto,
+    Ctest,
+    Chrome,
+    Tap,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(InputFormat::Auto),
+            "ctest" => Ok(InputFormat::Ctest),
+            "chrome" => Ok(InputFormat::Chrome),
+            "tap" => Ok(InputFormat::Tap),
+            _ => Err(format!("Unknown input format \"{}\"", value)),
+        }
+    }
+}
+
 /// Converts ctest result output into Chrome's trace event JSON format.
 ///
 /// The output is minified JSON, which one should be able to visualize
@@ -24,14 +140,403 @@ use structopt::StructOpt;
 #[structopt(name = "ctest_tracing", verbatim_doc_comment)]
 struct Opt {
     /// Input file, use '-' for stdin
-    #[structopt(parse(try_from_os_str = Input::try_from_os_str), default_value="-")]
+    /// gzip, zstd, and xz compressed input is decompressed
+    #[structopt(parse(try_from_os_str = Input::try_from_os_str), default_value="-", verbatim_doc_comment)]
     input: Input,
 
     /// Output file '-' for stdout
     /// When a file is specified any parent directories will be
     /// created if they don't exist
+    /// A file ending in `.gz` will be gzip compressed
     #[structopt(long, short, parse(try_from_os_str = try_from_os_str_with_parents), default_value="-", verbatim_doc_comment)]
     output: Output,
+
+    /// Gzip compress the output, regardless of the output file name
+    #[structopt(long)]
+    gzip: bool,
+
+    /// The format of the output
+    /// `chrome` is the Chrome trace event format
+    /// `junit` is a JUnit XML test suite
+    /// `github` is a Markdown job summary for GitHub Actions, with an
+    /// `::error` workflow command on stderr for each failed test
+    /// `teamcity` is TeamCity service messages reporting each test
+    /// `tap` is a TAP version 14 stream, with the times of each test
+    /// `csv` and `jsonl` are a row for each test, see --columns
+    /// `otlp` is an OpenTelemetry trace in OTLP/JSON, with a span for
+    /// the run and a child span for each test
+    #[structopt(long, default_value = "chrome", possible_values = &["chrome", "junit", "github", "teamcity", "tap", "csv", "jsonl", "otlp"], verbatim_doc_comment)]
+    format: OutputFormat,
+
+    /// The number of the longest tests listed by `--format github`
+    #[structopt(long, default_value = "10")]
+    slowest: usize,
+
+    /// The columns written by `--format csv` and `--format jsonl`,
+    /// comma separated, all of them by default
+    /// They're always in the order of the possible values
+    #[structopt(long, use_delimiter = true, possible_values = &COLUMN_NAMES, verbatim_doc_comment)]
+    columns: Vec<Column>,
+
+    /// An identifier for the run, i.e. the CI build number, written in
+    /// the run_id column of `--format csv` and `--format jsonl`, and
+    /// as the ctest.run_id attribute of the OpenTelemetry run span
+    #[structopt(long, verbatim_doc_comment)]
+    run_id: Option<String>,
+
+    /// The OTLP/HTTP traces endpoint of an OpenTelemetry collector to
+    /// send the run to, i.e. http://localhost:4318/v1/traces
+    /// The run is sent as well as written in the --format
+    #[structopt(long, verbatim_doc_comment)]
+    otlp_endpoint: Option<String>,
+
+    /// A header sent to the --otlp-endpoint, as NAME=VALUE, may be
+    /// repeated
+    #[structopt(long, parse(try_from_str = parse_header), number_of_values = 1, verbatim_doc_comment)]
+    otlp_header: Vec<(String, String)>,
+
+    /// Give up on the --otlp-endpoint when connecting to it, or its
+    /// reply, takes longer than this many seconds
+    #[structopt(long, default_value = "10", parse(try_from_str = parse_seconds), verbatim_doc_comment)]
+    otlp_timeout: Duration,
+
+    /// The service.name of the OpenTelemetry spans
+    #[structopt(long, default_value = "ctest")]
+    otlp_service_name: String,
+
+    /// The output of `ctest --show-only=json-v1` for the tests
+    /// Used to draw arrows from the end of each DEPENDS and
+    /// FIXTURES_SETUP/FIXTURES_REQUIRED/FIXTURES_CLEANUP
+    /// prerequisite to the start of the dependent test, and for the
+    /// LABELS of each test
+    #[structopt(long, global = true, parse(from_os_str), verbatim_doc_comment)]
+    test_info: Option<PathBuf>,
+
+    /// The number of processors a test uses, as NAME=COUNT
+    /// Overrides the PROCESSORS property from --test-info, may be
+    /// repeated
+    #[structopt(long, global = true, parse(try_from_str = parse_processors), number_of_values = 1, verbatim_doc_comment)]
+    processors: Vec<(String, u32)>,
+
+    /// The resource spec file given to ctest's --resource-spec-file
+    /// Each resource slot gets its own track showing the tests which
+    /// held it, requires the output of `ctest -V`
+    #[structopt(long, parse(from_os_str), verbatim_doc_comment)]
+    resource_spec_file: Option<PathBuf>,
+
+    /// Fail on problems in the ctest output, like a test finishing
+    /// which was never started
+    /// By default problems are reported to stderr, and in the trace's
+    /// metadata, and the rest of the output is still converted
+    #[structopt(long, global = true, verbatim_doc_comment)]
+    strict: bool,
+
+    /// A regular expression for a prefix to remove from each line,
+    /// may be repeated
+    /// MSBuild's `1>` and CMake Tools' `[ctest]` prefixes are always
+    /// removed, as are terminal colors and carriage returns
+    #[structopt(long, global = true, number_of_values = 1, verbatim_doc_comment)]
+    strip_prefix: Vec<Regex>,
+
+    /// Keep reading the input file as ctest writes to it, like
+    /// `tail -F`, until ctest prints its total test time
+    /// Each test is appended to the output as it finishes, so the
+    /// output can be opened while the tests are still running
+    /// Problems in the ctest output are added to an output file once
+    /// ctest finishes, but are only reported to stderr for stdout
+    #[structopt(long, verbatim_doc_comment)]
+    follow: bool,
+
+    /// With --follow, stop once the input file hasn't grown for this
+    /// many seconds, i.e. when ctest was killed before it finished
+    #[structopt(long, requires = "follow", parse(try_from_str = parse_seconds), verbatim_doc_comment)]
+    idle_timeout: Option<Duration>,
+
+    /// Show the tests running in each job lane in a full screen
+    /// dashboard on stderr while the ctest output is read
+    /// Press `q` to close the dashboard
+    #[structopt(long, verbatim_doc_comment)]
+    dashboard: bool,
+
+    /// The CTestCostData.txt with the average durations of previous
+    /// runs, shown on the dashboard
+    /// Defaults to Testing/Temporary/CTestCostData.txt, when run from
+    /// the build directory
+    #[structopt(long, parse(from_os_str), verbatim_doc_comment)]
+    cost_data: Option<PathBuf>,
+
+    /// The format of the input, either the ctest output, or a trace
+    /// previously written by ctest_tracing, or any other Chrome trace,
+    /// or a TAP stream
+    /// `auto` detects a trace from the input starting with JSON, and
+    /// TAP from the input starting with `TAP version`
+    #[structopt(long, global = true, default_value = "auto", possible_values = &["auto", "ctest", "chrome", "tap"], verbatim_doc_comment)]
+    from: InputFormat,
+
+    #[structopt(flatten)]
+    baseline: BaselineOpt,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+// The options for comparing the tests to previous runs, given to the
+// conversion and to `outliers`
+#[derive(StructOpt)]
+struct BaselineOpt {
+    /// The ctest output, or trace, of a previous run to compare the
+    /// tests to, may be repeated
+    /// Tests which were unusually slow compared to their previous
+    /// durations are reported, and colored red in the trace
+    #[structopt(long, parse(from_os_str), number_of_values = 1, verbatim_doc_comment)]
+    baseline: Vec<PathBuf>,
+
+    /// A timing history, see `record`, to compare the tests to
+    /// The last 20 runs are used, along with any --baseline runs
+    #[structopt(long, parse(from_os_str), verbatim_doc_comment)]
+    baseline_history: Option<PathBuf>,
+
+    /// How far above its baseline a test's duration must be to be
+    /// reported, in scaled median absolute deviations
+    #[structopt(long, default_value = &DEFAULT_OUTLIER_THRESHOLD, verbatim_doc_comment)]
+    outlier_threshold: f64,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Splits the tests into shards which take about the same time,
+    /// from the test durations in the ctest output or a trace
+    /// Prints each shard on its own line as the ctest arguments to
+    /// run only its tests
+    #[structopt(verbatim_doc_comment)]
+    Shard(ShardOpt),
+
+    /// Finds the tests which both passed and failed over a number of
+    /// runs, from the ctest output or trace of each run
+    /// Reports how often each flips between passing and failing, the
+    /// variance of its duration, and the runs it failed in
+    #[structopt(verbatim_doc_comment)]
+    Flaky(FlakyOpt),
+
+    /// Appends the tests of a run, from its ctest output or trace, to
+    /// the timing history
+    #[structopt(verbatim_doc_comment)]
+    Record(RecordOpt),
+
+    /// Shows each time a test ran in the last runs of the timing
+    /// history, with the median, 90th percentile, and trend of its
+    /// duration
+    #[structopt(verbatim_doc_comment)]
+    History(HistoryOpt),
+
+    /// Shows the median, 90th percentile, and trend of the duration of
+    /// every test in the last runs of the timing history, the tests
+    /// getting slower the fastest first
+    #[structopt(verbatim_doc_comment)]
+    Trends(TrendsOpt),
+
+    /// Reports the tests which were unusually slow compared to the
+    /// --baseline runs or --baseline-history
+    #[structopt(verbatim_doc_comment)]
+    Outliers(OutliersOpt),
+}
+
+// The ways to write a report, see `flaky --format`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("Unknown report format \"{}\"", value)),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+struct FlakyOpt {
+    /// The ctest output, or trace, of each run, oldest first
+    /// gzip, zstd, and xz compressed input is decompressed
+    #[structopt(parse(try_from_os_str = Input::try_from_os_str), required = true, min_values = 1, verbatim_doc_comment)]
+    inputs: Vec<Input>,
+
+    /// Output file '-' for stdout
+    #[structopt(long, short, parse(try_from_os_str = try_from_os_str_with_parents), default_value="-")]
+    output: Output,
+
+    /// How to write the report, as `text` or `json`
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: ReportFormat,
+}
+
+// The ways to write the planned shards, see `shard --format`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ShardFormat {
+    Args,
+    Json,
+    Cmake,
+}
+
+impl FromStr for ShardFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "args" => Ok(ShardFormat::Args),
+            "json" => Ok(ShardFormat::Json),
+            "cmake" => Ok(ShardFormat::Cmake),
+            _ => Err(format!("Unknown shard format \"{}\"", value)),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+struct ShardOpt {
+    /// Input file, use '-' for stdin
+    /// gzip, zstd, and xz compressed input is decompressed
+    #[structopt(parse(try_from_os_str = Input::try_from_os_str), default_value="-", verbatim_doc_comment)]
+    input: Input,
+
+    /// Output file '-' for stdout
+    #[structopt(long, short, parse(try_from_os_str = try_from_os_str_with_parents), default_value="-")]
+    output: Output,
+
+    /// The number of shards to split the tests into
+    #[structopt(long)]
+    shards: usize,
+
+    /// The number of tests each shard runs at once, ctest's `-j`
+    #[structopt(long, short = "j", default_value = "1")]
+    parallel: usize,
+
+    /// How to write the shards
+    /// `args` is a line of ctest arguments for each shard
+    /// `json` is a list of the tests and estimated time of each shard
+    /// `cmake` sets CTEST_SHARD_0, CTEST_SHARD_1, ... to the lists of
+    /// tests
+    #[structopt(long, default_value = "args", possible_values = &["args", "json", "cmake"], verbatim_doc_comment)]
+    format: ShardFormat,
+}
+
+#[derive(StructOpt)]
+struct RecordOpt {
+    /// Input file, use '-' for stdin
+    /// gzip, zstd, and xz compressed input is decompressed
+    #[structopt(parse(try_from_os_str = Input::try_from_os_str), default_value="-", verbatim_doc_comment)]
+    input: Input,
+
+    /// The timing history file, created if it doesn't exist
+    #[structopt(long, parse(from_os_str), default_value = DEFAULT_HISTORY)]
+    store: PathBuf,
+
+    /// A name for the run, i.e. the commit or CI job it's from
+    #[structopt(long)]
+    label: Option<String>,
+}
+
+#[derive(StructOpt)]
+struct HistoryOpt {
+    /// The name of the test
+    test: String,
+
+    /// The timing history file
+    #[structopt(long, parse(from_os_str), default_value = DEFAULT_HISTORY)]
+    store: PathBuf,
+
+    /// The number of most recent runs to look at
+    #[structopt(long, default_value = "20")]
+    last: usize,
+
+    /// Output file '-' for stdout
+    #[structopt(long, short, parse(try_from_os_str = try_from_os_str_with_parents), default_value="-")]
+    output: Output,
+
+    /// How to write the report, as `text` or `json`
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: ReportFormat,
+}
+
+#[derive(StructOpt)]
+struct TrendsOpt {
+    /// The timing history file
+    #[structopt(long, parse(from_os_str), default_value = DEFAULT_HISTORY)]
+    store: PathBuf,
+
+    /// The number of most recent runs to look at
+    #[structopt(long, default_value = "20")]
+    last: usize,
+
+    /// Output file '-' for stdout
+    #[structopt(long, short, parse(try_from_os_str = try_from_os_str_with_parents), default_value="-")]
+    output: Output,
+
+    /// How to write the report, as `text` or `json`
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: ReportFormat,
+}
+
+#[derive(StructOpt)]
+struct OutliersOpt {
+    /// Input file, use '-' for stdin
+    /// gzip, zstd, and xz compressed input is decompressed
+    #[structopt(parse(try_from_os_str = Input::try_from_os_str), default_value="-", verbatim_doc_comment)]
+    input: Input,
+
+    /// Output file '-' for stdout
+    #[structopt(long, short, parse(try_from_os_str = try_from_os_str_with_parents), default_value="-")]
+    output: Output,
+
+    /// How to write the report, as `text` or `json`
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: ReportFormat,
+
+    #[structopt(flatten)]
+    baseline: BaselineOpt,
+}
+
+// Parses a `NAME=COUNT` pair for the `--processors` option.
+fn parse_processors(value: &str) -> Result<(String, u32), String> {
+    let (name, count) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("Expected NAME=COUNT but got \"{}\"", value))?;
+    let count = count
+        .parse()
+        .map_err(|e| format!("Invalid processor count \"{}\": {}", count, e))?;
+    Ok((name.to_string(), count))
+}
+
+// Parses a number of seconds, which may have a fraction, i.e. `2.5`.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("Invalid number of seconds \"{}\"", value))
+}
+
+// Parses a `NAME=VALUE` pair for the `--otlp-header` option.
+fn parse_header(value: &str) -> Result<(String, String), String> {
+    let (name, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected NAME=VALUE but got \"{}\"", value))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+// Whether `output` is a file name ending in `.gz`
+fn is_gzip_file(output: &Output) -> bool {
+    match output {
+        Output::File(path, _) | Output::Pipe(path, _) => {
+            Path::new(path).extension() == Some(OsStr::new("gz"))
+        }
+        _ => false,
+    }
 }
 
 // Creates all parent directories for `path`.  If `path` has no parent
@@ -60,14 +565,563 @@ fn try_from_os_str_with_parents(path: &OsStr) -> std::result::Result<Output, std
     Output::try_from_os_str(path)
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut opt = Opt::from_args();
+// Reads the `--resource-spec-file`, if one was given
+fn read_resource_spec(
+    path: &Option<PathBuf>,
+) -> Result<Option<ResourceSpec>, Box<dyn std::error::Error>> {
+    match path {
+        Some(path) => Ok(Some(ResourceSpec::from_json(BufReader::new(File::open(
+            path,
+        )?))?)),
+        None => Ok(None),
+    }
+}
+
+// Shows the `--dashboard`, if requested
+fn start_dashboard(
+    show: bool,
+    cost_data: &Option<PathBuf>,
+    options: &ParseOptions,
+) -> Result<Option<TerminalDashboard>, Box<dyn std::error::Error>> {
+    if !show {
+        return Ok(None);
+    }
+    let cost_data = match cost_data {
+        Some(path) => CostData::from_reader(BufReader::new(File::open(path)?))?,
+        None => match File::open(DEFAULT_COST_DATA) {
+            Ok(file) => CostData::from_reader(BufReader::new(file))?,
+            Err(_) => CostData::default(),
+        },
+    };
+    let dashboard = Dashboard::new(cost_data, options.test_info.clone(), Instant::now());
+    Ok(Some(TerminalDashboard::start(dashboard)?))
+}
+
+// Parses the ctest output, as `parse_with_options()`, passing each event on
+// to the `dashboard` as it's seen
+fn parse_with_dashboard<R: Read>(
+    reader: BufReader<R>,
+    options: &ParseOptions,
+    dashboard: &TerminalDashboard,
+) -> Result<Run, Box<dyn std::error::Error>> {
+    let mut events = Events::new(reader, options.clone());
+    let mut run = Run::default();
+    for event in &mut events {
+        let event = event?;
+        dashboard.update(&event);
+        match event {
+            ParseEvent::TestFinished(trace) => run.traces.push(trace),
+            ParseEvent::Summary(summary) => run.summary = Some(summary),
+            ParseEvent::TestStarted { .. } => {}
+        }
+    }
+    run.diagnostics = events.parser().diagnostics().to_vec();
+    run.start_time = events.parser().start_time();
+    Ok(run)
+}
 
-    let reader = BufReader::new(opt.input.lock());
-    let traces = parse(reader)?;
+// Converts the input file as ctest writes it, streaming each test to the
+// output as it finishes.  Events which need every test, like the dependency
+// arrows, are written once ctest is done.
+fn follow(opt: &mut Opt, options: ParseOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let path = match &opt.input {
+        Input::File(path, _) => PathBuf::from(path),
+        _ => return Err("--follow requires an input file".into()),
+    };
+    if opt.gzip || is_gzip_file(&opt.output) {
+        return Err("--follow can't write gzip compressed output".into());
+    }
+    // A compressed file can't be decompressed until it's complete
+    let mut magic = vec![];
+    if let Ok(file) = File::open(&path) {
+        file.take(8).read_to_end(&mut magic)?;
+    }
+    if Compression::detect(&magic) != Compression::None {
+        return Err("--follow can't read compressed input".into());
+    }
+    let spec = read_resource_spec(&opt.resource_spec_file)?;
+    let baseline = read_baseline(&opt.baseline, opt.from, &options)?;
 
-    let serialized_data = serde_json::to_string(&traces)?;
-    opt.output.write_all(serialized_data.as_bytes())?;
+    let mut follower = Follower::new(path, FOLLOW_POLL_INTERVAL);
+    follower.set_idle_timeout(opt.idle_timeout);
+    // The log being truncated or replaced means ctest is running again, so
+    // the trace starts over
+    while follow_run(opt, &options, &spec, &baseline, &mut follower)? {
+        if !rewind(&mut opt.output)? {
+            eprintln!(
+                "warning: The log was rewritten, following stopped as the output can't be emptied"
+            );
+            break;
+        }
+    }
+    Ok(())
+}
 
+// Streams a single run of ctest from `follower` to the output, until ctest
+// prints its total test time or the idle timeout passes.  Returns true when
+// the log was truncated or replaced first.
+fn follow_run(
+    opt: &mut Opt,
+    options: &ParseOptions,
+    spec: &Option<ResourceSpec>,
+    baseline: &Option<Baseline>,
+    follower: &mut Follower,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let dashboard = start_dashboard(opt.dashboard, &opt.cost_data, options)?;
+    let mut events = Events::new(BufReader::new(&mut *follower), options.clone());
+    let mut stream = StreamWriter::new(opt.output.lock())?;
+    let mut traces = vec![];
+    let mut reported = 0;
+    while let Some(event) = events.next() {
+        let event = event?;
+        if let Some(dashboard) = &dashboard {
+            dashboard.update(&event);
+        }
+        let done = match event {
+            ParseEvent::TestFinished(mut trace) => {
+                if let Some(baseline) = baseline {
+                    trace.outlier = baseline.outlier(&trace, opt.baseline.outlier_threshold);
+                }
+                for thread in trace.per_thread() {
+                    stream.write_event(&Event::Complete(thread))?;
+                }
+                traces.push(trace);
+                false
+            }
+            ParseEvent::Summary(_) => true,
+            ParseEvent::TestStarted { .. } => false,
+        };
+        // Warnings would garble the dashboard, so they wait until it's closed
+        if dashboard.is_none() {
+            let diagnostics = events.parser().diagnostics();
+            for diagnostic in &diagnostics[reported..] {
+                eprintln!("warning: {}", diagnostic);
+            }
+            reported = diagnostics.len();
+        }
+        if done {
+            break;
+        }
+    }
+    if let Some(dashboard) = dashboard {
+        dashboard.finish()?;
+        for diagnostic in events.parser().diagnostics() {
+            eprintln!("warning: {}", diagnostic);
+        }
+    }
+    let diagnostics = events.parser().diagnostics().to_vec();
+    drop(events);
+    if follower.restarted() {
+        // Closed, so output which can't be rewound has a complete trace of
+        // each run
+        stream.finish()?;
+        return Ok(true);
+    }
+    write_outlier_warnings(std::io::stderr(), &traces)?;
+
+    if opt.test_info.is_some() {
+        for flow in flow_events(&traces, &options.test_info) {
+            stream.write_event(&Event::Flow(flow))?;
+        }
+    }
+    if let Some(spec) = spec {
+        for metadata in spec.metadata() {
+            stream.write_event(&Event::Metadata(metadata))?;
+        }
+        for resource in resource_events(spec, &traces) {
+            stream.write_event(&Event::Resource(resource))?;
+        }
+    }
+    stream.finish()?;
+
+    // The streaming form can't hold the diagnostics, so a file is rewritten
+    // with them, the same as without --follow
+    if !diagnostics.is_empty() && rewind(&mut opt.output)? {
+        let events = chrome_events(opt, options, &traces)?;
+        write_json(opt.output.lock(), &events, &diagnostics)?;
+    }
+    Ok(false)
+}
+
+// Empties the output file so the trace can start over.  Returns false for
+// other outputs, which can't be rewound.
+fn rewind(output: &mut Output) -> std::io::Result<bool> {
+    match output {
+        Output::File(_, file) => {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+// Prints `error`, with the line of ctest output it's about for parse errors
+fn print_error(error: &(dyn std::error::Error + 'static)) {
+    match error.downcast_ref::<ParseError>() {
+        Some(error) => eprintln!("{}", error.snippet()),
+        None => eprintln!("error: {}", error),
+    }
+}
+
+fn main() {
+    if let Err(error) = run(Opt::from_args()) {
+        print_error(error.as_ref());
+        std::process::exit(1);
+    }
+}
+
+// The options for parsing ctest output given on the command line
+fn parse_options(opt: &Opt) -> Result<ParseOptions, Box<dyn std::error::Error>> {
+    let mut options = ParseOptions {
+        lenient: !opt.strict,
+        ..Default::default()
+    };
+    if let Some(path) = &opt.test_info {
+        options.test_info = TestInfo::from_json_v1(BufReader::new(File::open(path)?))?;
+    }
+    for prefix in &opt.strip_prefix {
+        options.normalizer.add_prefix(prefix.clone());
+    }
+    for (name, count) in &opt.processors {
+        options.test_info.set_processors(name, *count);
+    }
+    Ok(options)
+}
+
+// Reads the tests from `input`, either ctest output or a Chrome trace as
+// `from` says, showing the `--dashboard` while reading ctest output
+fn read_run(
+    input: &mut Input,
+    from: InputFormat,
+    options: &ParseOptions,
+    show_dashboard: bool,
+    cost_data: &Option<PathBuf>,
+) -> Result<Run, Box<dyn std::error::Error>> {
+    let mut input = decompress(input.lock())?;
+    let mut start = vec![];
+    (&mut input)
+        .take(FORMAT_DETECTION_BYTES)
+        .read_to_end(&mut start)?;
+    let is_trace = is_trace_json(&start);
+    let is_tap_stream = is_tap(&start);
+    let reader = BufReader::new(Cursor::new(start).chain(input));
+    let from = match from {
+        InputFormat::Auto if is_trace => InputFormat::Chrome,
+        InputFormat::Auto if is_tap_stream => InputFormat::Tap,
+        InputFormat::Auto => InputFormat::Ctest,
+        from => from,
+    };
+    if from != InputFormat::Ctest && show_dashboard {
+        return Err("--dashboard only shows ctest output".into());
+    }
+    match from {
+        InputFormat::Chrome => return Ok(read_json(reader)?),
+        InputFormat::Tap => return Ok(read_tap(reader)?),
+        _ => {}
+    }
+
+    let run = match start_dashboard(show_dashboard, cost_data, options)? {
+        Some(dashboard) => {
+            let run = parse_with_dashboard(reader, options, &dashboard)?;
+            dashboard.finish()?;
+            run
+        }
+        None => parse_with_options(reader, options)?,
+    };
+    for diagnostic in &run.diagnostics {
+        eprintln!("warning: {}", diagnostic);
+    }
+    Ok(run)
+}
+
+// Writes the shards planned from the tests in the input
+fn shard(shard_opt: &mut ShardOpt, opt: &Opt) -> Result<(), Box<dyn std::error::Error>> {
+    if shard_opt.shards == 0 {
+        return Err("--shards must be at least 1".into());
+    }
+    let options = parse_options(opt)?;
+    let run = read_run(&mut shard_opt.input, opt.from, &options, false, &None)?;
+    let shards = plan(&run.traces, shard_opt.shards, shard_opt.parallel);
+    // An empty shard has no ctest arguments which select no tests
+    if shards.iter().any(|s| s.tests.is_empty()) {
+        let tests: usize = shards.iter().map(|s| s.tests.len()).sum();
+        return Err(format!(
+            "--shards {} is more than the {} test{} in the input",
+            shard_opt.shards,
+            tests,
+            if tests == 1 { "" } else { "s" }
+        )
+        .into());
+    }
+
+    let output = &mut shard_opt.output;
+    match shard_opt.format {
+        ShardFormat::Args => {
+            for shard in &shards {
+                writeln!(output, "{}", shard.ctest_arguments())?;
+            }
+        }
+        ShardFormat::Json => {
+            serde_json::to_writer(&mut *output, &shards)?;
+            writeln!(output)?;
+        }
+        ShardFormat::Cmake => {
+            for (index, shard) in shards.iter().enumerate() {
+                writeln!(
+                    output,
+                    "{}",
+                    shard.cmake_list(&format!("CTEST_SHARD_{}", index))
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Writes a report of the tests which flip between passing and failing over
+// the runs in the inputs
+fn flaky(flaky_opt: &mut FlakyOpt, opt: &Opt) -> Result<(), Box<dyn std::error::Error>> {
+    let options = parse_options(opt)?;
+    let mut runs = vec![];
+    for input in &mut flaky_opt.inputs {
+        runs.push(read_run(input, opt.from, &options, false, &None)?.traces);
+    }
+    let names: Vec<String> = flaky_opt
+        .inputs
+        .iter()
+        .map(|input| input.path().to_string_lossy().into_owned())
+        .collect();
+    let flaky = find_flaky(&runs);
+
+    let output = &mut flaky_opt.output;
+    match flaky_opt.format {
+        ReportFormat::Text => write_flaky_text(output, &flaky, &names)?,
+        ReportFormat::Json => write_flaky_json(output, &flaky, &names)?,
+    }
+    Ok(())
+}
+
+// Adds the run in the input to the timing history
+fn record(record_opt: &mut RecordOpt, opt: &Opt) -> Result<(), Box<dyn std::error::Error>> {
+    let options = parse_options(opt)?;
+    let run = read_run(&mut record_opt.input, opt.from, &options, false, &None)?;
+    let source = record_opt.input.path().to_string_lossy().into_owned();
+    let recorded = RecordedRun::new(&run, &source, record_opt.label.clone(), SystemTime::now());
+    history::append(&record_opt.store, &recorded)?;
+    Ok(())
+}
+
+// Reads the runs in the timing history at `path`
+fn read_history(path: &Path) -> Result<Vec<RecordedRun>, Box<dyn std::error::Error>> {
+    let file = File::open(path).map_err(|e| {
+        format!(
+            "Failed to open the timing history \"{}\", runs are added to it with `record`: {}",
+            path.display(),
+            e
+        )
+    })?;
+    Ok(history::read(BufReader::new(file))?)
+}
+
+// Writes each time a test ran in the timing history, and its trend
+fn show_history(history_opt: &mut HistoryOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let runs = read_history(&history_opt.store)?;
+    let test_runs = test_history(&runs, &history_opt.test, history_opt.last);
+    if test_runs.is_empty() {
+        return Err(format!(
+            "\"{}\" isn't in the last {} runs of the timing history",
+            history_opt.test, history_opt.last
+        )
+        .into());
+    }
+    let output = &mut history_opt.output;
+    match history_opt.format {
+        ReportFormat::Text => write_history_text(output, &history_opt.test, &test_runs)?,
+        ReportFormat::Json => write_history_json(output, &history_opt.test, &test_runs)?,
+    }
+    Ok(())
+}
+
+// Writes the trend of every test in the timing history
+fn show_trends(trends_opt: &mut TrendsOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let runs = read_history(&trends_opt.store)?;
+    let trends = trends(&runs, trends_opt.last);
+
+    let output = &mut trends_opt.output;
+    match trends_opt.format {
+        ReportFormat::Text => write_trends_text(output, &trends)?,
+        ReportFormat::Json => write_trends_json(output, &trends)?,
+    }
+    Ok(())
+}
+
+// The previous durations of the tests from `--baseline` and
+// `--baseline-history`, if either was given
+fn read_baseline(
+    baseline_opt: &BaselineOpt,
+    from: InputFormat,
+    options: &ParseOptions,
+) -> Result<Option<Baseline>, Box<dyn std::error::Error>> {
+    if baseline_opt.baseline.is_empty() && baseline_opt.baseline_history.is_none() {
+        return Ok(None);
+    }
+    let mut baseline = Baseline::default();
+    for path in &baseline_opt.baseline {
+        let mut input = Input::try_from_os_str(path.as_os_str())
+            .map_err(|e| e.to_string_lossy().into_owned())?;
+        let run = read_run(&mut input, from, options, false, &None)?;
+        baseline.add_traces(&run.traces);
+    }
+    if let Some(path) = &baseline_opt.baseline_history {
+        baseline.add_history(&read_history(path)?, BASELINE_HISTORY_RUNS);
+    }
+    Ok(Some(baseline))
+}
+
+// Writes a report of the tests in the input which were unusually slow
+fn outliers(outliers_opt: &mut OutliersOpt, opt: &Opt) -> Result<(), Box<dyn std::error::Error>> {
+    let options = parse_options(opt)?;
+    let baseline = read_baseline(&outliers_opt.baseline, opt.from, &options)?
+        .ok_or("outliers requires a --baseline or --baseline-history")?;
+    let mut run = read_run(&mut outliers_opt.input, opt.from, &options, false, &None)?;
+    baseline.mark_outliers(&mut run.traces, outliers_opt.baseline.outlier_threshold);
+
+    let output = &mut outliers_opt.output;
+    match outliers_opt.format {
+        ReportFormat::Text => write_outliers_text(output, &run.traces)?,
+        ReportFormat::Json => write_outliers_json(output, &run.traces)?,
+    }
+    Ok(())
+}
+
+// The OpenTelemetry spans of `run`.  Without timestamps in the ctest output
+// the run is taken to have just finished.
+fn otlp_request(opt: &Opt, run: &Run) -> std::io::Result<ExportRequest> {
+    let start_time = run
+        .start_time
+        .unwrap_or_else(|| SystemTime::now() - run.wall_time());
+    let export = Export {
+        service_name: &opt.otlp_service_name,
+        run_id: opt.run_id.as_deref(),
+        start_time,
+        trace_id: TraceId::random()?,
+    };
+    Ok(export_request(run, &export))
+}
+
+// The events of the Chrome trace for `traces`, with the dependency arrows
+// and resource tracks when requested
+fn chrome_events(
+    opt: &Opt,
+    options: &ParseOptions,
+    traces: &[Trace],
+) -> Result<Vec<Event>, Box<dyn std::error::Error>> {
+    let flows = match &opt.test_info {
+        Some(_) => flow_events(traces, &options.test_info),
+        None => vec![],
+    };
+
+    let (resource_metadata, resources) = match read_resource_spec(&opt.resource_spec_file)? {
+        Some(spec) => (spec.metadata(), resource_events(&spec, traces)),
+        None => (vec![], vec![]),
+    };
+
+    Ok(traces
+        .iter()
+        .flat_map(|t| t.per_thread())
+        .map(Event::Complete)
+        .chain(flows.into_iter().map(Event::Flow))
+        .chain(resource_metadata.into_iter().map(Event::Metadata))
+        .chain(resources.into_iter().map(Event::Resource))
+        .collect())
+}
+
+fn run(mut opt: Opt) -> Result<(), Box<dyn std::error::Error>> {
+    match opt.command.take() {
+        Some(Command::Shard(mut shard_opt)) => return shard(&mut shard_opt, &opt),
+        Some(Command::Flaky(mut flaky_opt)) => return flaky(&mut flaky_opt, &opt),
+        Some(Command::Record(mut record_opt)) => return record(&mut record_opt, &opt),
+        Some(Command::History(mut history_opt)) => return show_history(&mut history_opt),
+        Some(Command::Trends(mut trends_opt)) => return show_trends(&mut trends_opt),
+        Some(Command::Outliers(mut outliers_opt)) => return outliers(&mut outliers_opt, &opt),
+        None => {}
+    }
+
+    let options = parse_options(&opt)?;
+    if opt.follow {
+        if matches!(opt.from, InputFormat::Chrome | InputFormat::Tap) {
+            return Err("--follow only reads ctest output".into());
+        }
+        if opt.format != OutputFormat::Chrome {
+            return Err("--follow only writes Chrome traces".into());
+        }
+        if opt.otlp_endpoint.is_some() {
+            return Err("--otlp-endpoint only sends a finished run".into());
+        }
+        return follow(&mut opt, options);
+    }
+
+    let mut run = read_run(
+        &mut opt.input,
+        opt.from,
+        &options,
+        opt.dashboard,
+        &opt.cost_data,
+    )?;
+    if let Some(baseline) = read_baseline(&opt.baseline, opt.from, &options)? {
+        baseline.mark_outliers(&mut run.traces, opt.baseline.outlier_threshold);
+        write_outlier_warnings(std::io::stderr(), &run.traces)?;
+    }
+
+    // The same spans are written and sent, so they have the same trace ID
+    let otlp = if opt.format == OutputFormat::Otlp || opt.otlp_endpoint.is_some() {
+        Some(otlp_request(&opt, &run)?)
+    } else {
+        None
+    };
+
+    let mut serialized_data = vec![];
+    match opt.format {
+        OutputFormat::Chrome => {
+            let events = chrome_events(&opt, &options, &run.traces)?;
+            write_json(&mut serialized_data, &events, &run.diagnostics)?;
+        }
+        OutputFormat::Junit => write_junit(&mut serialized_data, &run, JUNIT_SUITE_NAME)?,
+        OutputFormat::Github => {
+            write_summary(&mut serialized_data, &run, opt.slowest)?;
+            write_annotations(std::io::stderr().lock(), &run.traces)?;
+        }
+        OutputFormat::Teamcity => write_service_messages(&mut serialized_data, &run.traces)?,
+        OutputFormat::Tap => write_tap(&mut serialized_data, &run)?,
+        OutputFormat::Csv => write_csv(
+            &mut serialized_data,
+            &run.traces,
+            &opt.columns,
+            opt.run_id.as_deref(),
+        )?,
+        OutputFormat::Jsonl => write_jsonl(
+            &mut serialized_data,
+            &run.traces,
+            &opt.columns,
+            opt.run_id.as_deref(),
+        )?,
+        OutputFormat::Otlp => write_otlp_json(
+            &mut serialized_data,
+            otlp.as_ref().expect("built for --format otlp"),
+        )?,
+    }
+    if opt.gzip || is_gzip_file(&opt.output) {
+        let mut encoder = GzEncoder::new(opt.output.lock(), flate2::Compression::default());
+        encoder.write_all(&serialized_data)?;
+        encoder.finish()?;
+    } else {
+        opt.output.write_all(&serialized_data)?;
+    }
+
+    // Sent last, so the output is still written when the collector is down
+    if let (Some(endpoint), Some(request)) = (&opt.otlp_endpoint, &otlp) {
+        send(endpoint, &opt.otlp_header, opt.otlp_timeout, request)?;
+    }
     Ok(())
 }
@@ -0,0 +1,150 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use regex::Regex;
+use std::borrow::Cow;
+
+//  Terminal escape sequences, i.e. colors, which may be mixed in with the
+//  ctest output:
+//
+//      ESC [ <parameters> <intermediates> <final>       (CSI, colors and cursor)
+//      ESC ] <text> BEL | ESC \                         (OSC, titles and links)
+//      ESC <final>                                      (everything else)
+//
+const ESCAPES: &str = r"\x1b(\[[0-?]*[ -/]*[@-~]|\][^\x07\x1b]*(\x07|\x1b\\)?|[@-_])";
+
+/// The prefixes build tools put in front of the ctest output, which are
+/// removed by default.
+///
+/// - `1>` from MSBuild when running the `RUN_TESTS` target
+/// - `[ctest]` from the CMake Tools extension for VS Code
+pub const DEFAULT_PREFIXES: &[&str] = &[r"^\s*\d+>", r"^\[ctest\]"];
+
+/// Cleans up a line of a log so the ctest output in it can be parsed.
+///
+/// Terminal escape sequences and carriage returns are removed, then any
+/// configured prefixes, i.e. from the build tool running ctest.
+#[derive(Debug, Clone)]
+pub struct Normalizer {
+    escapes: Regex,
+    prefixes: Vec<Regex>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        let prefixes = DEFAULT_PREFIXES
+            .iter()
+            .map(|p| Regex::new(p).expect("default prefixes are valid"))
+            .collect();
+        Normalizer::new(prefixes)
+    }
+}
+
+impl Normalizer {
+    /// Creates a normalizer which removes the provided `prefixes`.
+    ///
+    /// A prefix is only removed when it matches at the start of the line, so
+    /// each should start with `^`.
+    pub fn new(prefixes: Vec<Regex>) -> Normalizer {
+        Normalizer {
+            escapes: Regex::new(ESCAPES).expect("escape pattern is valid"),
+            prefixes,
+        }
+    }
+
+    /// Adds another prefix to remove, after those already configured.
+    pub fn add_prefix(&mut self, prefix: Regex) {
+        self.prefixes.push(prefix);
+    }
+
+    /// Returns `line` with escape sequences, carriage returns, and prefixes
+    /// removed.
+    pub fn normalize<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let mut line = self.escapes.replace_all(line, "");
+        if line.contains('\r') {
+            line = Cow::Owned(line.replace('\r', ""));
+        }
+        for prefix in &self.prefixes {
+            if let Some(found) = prefix.find(&line) {
+                if found.start() == 0 {
+                    line = Cow::Owned(line[found.end()..].to_string());
+                }
+            }
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_line_is_unchanged() {
+        let line = "    Start 1: a_test";
+        let normalizer = Normalizer::default();
+        assert_eq!(normalizer.normalize(line), line);
+    }
+
+    #[test]
+    fn test_colored_line() {
+        let line = "1/1 Test #1: a_test .....   \x1b[32mPassed\x1b[0m    0.20 sec";
+        let normalizer = Normalizer::default();
+        assert_eq!(
+            normalizer.normalize(line),
+            "1/1 Test #1: a_test .....   Passed    0.20 sec"
+        );
+    }
+
+    #[test]
+    fn test_other_escapes() {
+        let line = "\x1b]0;title\x07\x1b[2K\x1b[1;31m\x1bM    Start 1: a_test\x1b]8;;\x1b\\";
+        let normalizer = Normalizer::default();
+        assert_eq!(normalizer.normalize(line), "    Start 1: a_test");
+    }
+
+    #[test]
+    fn test_carriage_returns() {
+        let line = "    Start 1: a_test\r\r";
+        let normalizer = Normalizer::default();
+        assert_eq!(normalizer.normalize(line), "    Start 1: a_test");
+    }
+
+    #[test]
+    fn test_msbuild_prefix() {
+        let line = "  12>      Start 3: a_test";
+        let normalizer = Normalizer::default();
+        assert_eq!(normalizer.normalize(line), "      Start 3: a_test");
+    }
+
+    #[test]
+    fn test_cmake_tools_prefix() {
+        let line = "[ctest]     Start 3: a_test";
+        let normalizer = Normalizer::default();
+        assert_eq!(normalizer.normalize(line), "     Start 3: a_test");
+    }
+
+    #[test]
+    fn test_prefix_only_removed_from_start() {
+        let line = "    Start 3: a>b";
+        let normalizer = Normalizer::new(vec![Regex::new(r"a>").unwrap()]);
+        assert_eq!(normalizer.normalize(line), line);
+    }
+
+    #[test]
+    fn test_custom_prefix() {
+        let line = "[build 7] |     Start 3: a_test";
+        let mut normalizer = Normalizer::default();
+        normalizer.add_prefix(Regex::new(r"^\[build \d+\] \|").unwrap());
+        assert_eq!(normalizer.normalize(line), "     Start 3: a_test");
+    }
+
+    #[test]
+    fn test_colored_msbuild_prefix() {
+        let line = "\x1b[36m1>\x1b[0m      Start 3: a_test\r";
+        let normalizer = Normalizer::default();
+        assert_eq!(normalizer.normalize(line), "      Start 3: a_test");
+    }
+}
@@ -0,0 +1,469 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::parser::Run;
+use crate::trace::{TestStatus, Trace};
+
+use serde::Serialize;
+use std::io;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The span ID of the root span of the run, the tests follow it
+const ROOT_SPAN_ID: u64 = 1;
+
+// Span kinds and status codes, as numbers as allowed by the protobuf JSON
+// mapping
+const SPAN_KIND_INTERNAL: u32 = 1;
+const STATUS_CODE_UNSET: u32 = 0;
+const STATUS_CODE_OK: u32 = 1;
+const STATUS_CODE_ERROR: u32 = 2;
+
+/// The 16 byte ID shared by the spans of an exported run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceId(pub [u8; 16]);
+
+impl TraceId {
+    /// A new ID from the operating system's random number generator, so
+    /// runs exported to the same collector don't collide.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::Error`](io::Error) when the random number generator
+    /// isn't available.
+    pub fn random() -> io::Result<TraceId> {
+        let mut id = [0; 16];
+        getrandom::getrandom(&mut id)?;
+        Ok(TraceId(id))
+    }
+
+    fn to_hex(self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// What's needed to export a run, beyond its tests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Export<'a> {
+    /// The `service.name` of the resource the spans come from
+    pub service_name: &'a str,
+    /// An identifier for the run, i.e. the CI build number
+    pub run_id: Option<&'a str>,
+    /// When the run started, the tests' start times are offsets from this
+    pub start_time: SystemTime,
+    pub trace_id: TraceId,
+}
+
+//  The subset of the OTLP/JSON `ExportTraceServiceRequest` which is written,
+//  see https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding
+//
+//      {"resourceSpans": [{
+//        "resource": {"attributes": [...]},
+//        "scopeSpans": [{"scope": {"name": "string", "version": "string"},
+//                        "spans": [...]}]
+//      }]}
+
+/// An OTLP/JSON trace export request, with the spans of a run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRequest {
+    resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    resource: Resource,
+    scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ScopeSpans {
+    scope: Scope,
+    spans: Vec<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Scope {
+    name: &'static str,
+    version: &'static str,
+}
+
+//  A span, the 64 bit times are strings in the JSON mapping:
+//
+//      {"traceId": "hex", "spanId": "hex", "parentSpanId": "hex",
+//       "name": "string", "kind": int,
+//       "startTimeUnixNano": "int", "endTimeUnixNano": "int",
+//       "attributes": [...], "status": {"code": int, "message": "string"}}
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Span {
+    trace_id: String,
+    span_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_span_id: Option<String>,
+    name: String,
+    kind: u32,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<KeyValue>,
+    status: Status,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Status {
+    code: u32,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct KeyValue {
+    key: &'static str,
+    value: AnyValue,
+}
+
+// 64 bit integers are strings in the JSON mapping
+#[derive(Debug, Clone, PartialEq, Serialize)]
+enum AnyValue {
+    #[serde(rename = "stringValue")]
+    String(String),
+    #[serde(rename = "intValue")]
+    Int(String),
+    #[serde(rename = "arrayValue")]
+    Array { values: Vec<AnyValue> },
+}
+
+fn attribute(key: &'static str, value: AnyValue) -> KeyValue {
+    KeyValue { key, value }
+}
+
+fn string(value: &str) -> AnyValue {
+    AnyValue::String(value.to_string())
+}
+
+fn int<T: ToString>(value: T) -> AnyValue {
+    AnyValue::Int(value.to_string())
+}
+
+fn span_id(id: u64) -> String {
+    format!("{:016x}", id)
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+// The span of a test, a child of the root span
+fn test_span(trace: &Trace, id: u64, export: &Export) -> Span {
+    let start = export.start_time + trace.start;
+    let (result, code) = match &trace.status {
+        TestStatus::Passed => ("pass", STATUS_CODE_OK),
+        TestStatus::Skipped | TestStatus::NotRun => ("skip", STATUS_CODE_UNSET),
+        _ => ("fail", STATUS_CODE_ERROR),
+    };
+    let mut attributes = vec![
+        attribute("test.case.name", string(&trace.name)),
+        attribute("test.case.result.status", string(result)),
+        attribute("ctest.status", string(&trace.status.to_string())),
+        attribute("ctest.lane", int(trace.thread_number)),
+    ];
+    if let Some(number) = trace.number {
+        attributes.push(attribute("ctest.test.number", int(number)));
+    }
+    if !trace.labels.is_empty() {
+        let values = trace.labels.iter().map(|l| string(l)).collect();
+        attributes.push(attribute("ctest.labels", AnyValue::Array { values }));
+    }
+    Span {
+        trace_id: export.trace_id.to_hex(),
+        span_id: span_id(id),
+        parent_span_id: Some(span_id(ROOT_SPAN_ID)),
+        name: trace.name.clone(),
+        kind: SPAN_KIND_INTERNAL,
+        start_time_unix_nano: unix_nanos(start),
+        end_time_unix_nano: unix_nanos(start + trace.duration),
+        attributes,
+        status: Status {
+            code,
+            message: match code {
+                STATUS_CODE_ERROR => trace.status.to_string(),
+                _ => String::new(),
+            },
+        },
+    }
+}
+
+/// Builds the OTLP trace export request for `run`.
+///
+/// The run is a root span named `ctest`, lasting the wall time of the run,
+/// with a child span for each test.  A test's span has the test's name, its
+/// status as an `ERROR` when it failed, timed out, or crashed, and attributes
+/// for its status, lane, number, and labels.
+pub fn export_request(run: &Run, export: &Export) -> ExportRequest {
+    let failed = run.traces.iter().filter(|t| t.status.is_failure()).count();
+    let end = run
+        .traces
+        .iter()
+        .map(|t| t.start + t.duration)
+        .fold(run.wall_time(), Duration::max);
+
+    let mut attributes = vec![
+        attribute("test.suite.name", string("ctest")),
+        attribute(
+            "test.suite.run.status",
+            string(if failed == 0 { "success" } else { "failure" }),
+        ),
+        attribute("ctest.tests", int(run.traces.len())),
+        attribute("ctest.failed", int(failed)),
+    ];
+    if let Some(run_id) = export.run_id {
+        attributes.push(attribute("ctest.run_id", string(run_id)));
+    }
+    let root = Span {
+        trace_id: export.trace_id.to_hex(),
+        span_id: span_id(ROOT_SPAN_ID),
+        parent_span_id: None,
+        name: "ctest".into(),
+        kind: SPAN_KIND_INTERNAL,
+        start_time_unix_nano: unix_nanos(export.start_time),
+        end_time_unix_nano: unix_nanos(export.start_time + end),
+        attributes,
+        status: Status {
+            code: if failed == 0 {
+                STATUS_CODE_OK
+            } else {
+                STATUS_CODE_ERROR
+            },
+            message: match failed {
+                0 => String::new(),
+                1 => "1 test failed".into(),
+                n => format!("{} tests failed", n),
+            },
+        },
+    };
+
+    let mut spans = vec![root];
+    for (id, trace) in (ROOT_SPAN_ID + 1..).zip(&run.traces) {
+        spans.push(test_span(trace, id, export));
+    }
+    ExportRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Resource {
+                attributes: vec![attribute("service.name", string(export.service_name))],
+            },
+            scope_spans: vec![ScopeSpans {
+                scope: Scope {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                spans,
+            }],
+        }],
+    }
+}
+
+/// Writes `request` as OTLP/JSON, as read by the OpenTelemetry Collector's
+/// `otlpjsonfile` receiver.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_otlp_json<W: Write>(mut writer: W, request: &ExportRequest) -> io::Result<()> {
+    serde_json::to_writer(&mut writer, request)?;
+    writeln!(writer)
+}
+
+/// Sends `request` to the OTLP/HTTP traces `endpoint` of a collector, i.e.
+/// `http://localhost:4318/v1/traces`, with the extra `headers`, like the
+/// authorization a hosted collector needs.  Connecting, sending the request,
+/// and reading the reply are each given up on after `timeout`.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when the collector can't be reached,
+/// doesn't reply within the `timeout`, or doesn't accept the request.
+pub fn send(
+    endpoint: &str,
+    headers: &[(String, String)],
+    timeout: Duration,
+    request: &ExportRequest,
+) -> io::Result<()> {
+    let body = serde_json::to_vec(request)?;
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(timeout)
+        .timeout_write(timeout)
+        .timeout_read(timeout)
+        .build();
+    let mut http = agent.post(endpoint).set("Content-Type", "application/json");
+    for (name, value) in headers {
+        http = http.set(name, value);
+    }
+    http.send_bytes(&body).map(|_| ()).map_err(|e| {
+        let reason = match e {
+            ureq::Error::Status(code, response) => {
+                let body = response.into_string().unwrap_or_default();
+                format!("status {} {}", code, body.trim())
+                    .trim_end()
+                    .to_string()
+            }
+            e => e.to_string(),
+        };
+        io::Error::other(format!(
+            "The OTLP collector at {} didn't accept the spans: {}",
+            endpoint, reason
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Summary;
+    use serde_json::{json, Value};
+
+    fn run() -> Run {
+        Run {
+            traces: vec![
+                Trace::new("test_one", Duration::new(0, 0), Duration::from_millis(200))
+                    .with_number(1)
+                    .with_labels(vec!["db".into()]),
+                Trace::new(
+                    "test_two",
+                    Duration::from_millis(100),
+                    Duration::from_millis(300),
+                )
+                .with_thread_number(1)
+                .with_status(TestStatus::Timeout),
+                Trace::new(
+                    "test_three",
+                    Duration::from_millis(200),
+                    Duration::new(0, 0),
+                )
+                .with_status(TestStatus::Skipped),
+            ],
+            summary: Some(Summary {
+                total: 3,
+                failed: 1,
+                total_time: Duration::from_millis(450),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn export() -> Export<'static> {
+        Export {
+            service_name: "ci",
+            run_id: Some("42"),
+            start_time: UNIX_EPOCH + Duration::from_secs(1_637_402_400),
+            trace_id: TraceId([0xab; 16]),
+        }
+    }
+
+    fn to_json(request: &ExportRequest) -> Value {
+        serde_json::to_value(request).unwrap()
+    }
+
+    #[test]
+    fn test_root_span() {
+        let json = to_json(&export_request(&run(), &export()));
+        let resource = &json["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"],
+            json!([{"key": "service.name", "value": {"stringValue": "ci"}}])
+        );
+        assert_eq!(resource["scopeSpans"][0]["scope"]["name"], "ctest_tracing");
+        let root = &resource["scopeSpans"][0]["spans"][0];
+        let expected = json!({
+            "traceId": "abababababababababababababababab",
+            "spanId": "0000000000000001",
+            "name": "ctest",
+            "kind": 1,
+            "startTimeUnixNano": "1637402400000000000",
+            "endTimeUnixNano": "1637402400450000000",
+            "attributes": [
+                {"key": "test.suite.name", "value": {"stringValue": "ctest"}},
+                {"key": "test.suite.run.status", "value": {"stringValue": "failure"}},
+                {"key": "ctest.tests", "value": {"intValue": "3"}},
+                {"key": "ctest.failed", "value": {"intValue": "1"}},
+                {"key": "ctest.run_id", "value": {"stringValue": "42"}}
+            ],
+            "status": {"code": 2, "message": "1 test failed"}
+        });
+        assert_eq!(root, &expected);
+    }
+
+    #[test]
+    fn test_test_spans() {
+        let json = to_json(&export_request(&run(), &export()));
+        let spans = &json["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        let expected = json!({
+            "traceId": "abababababababababababababababab",
+            "spanId": "0000000000000002",
+            "parentSpanId": "0000000000000001",
+            "name": "test_one",
+            "kind": 1,
+            "startTimeUnixNano": "1637402400000000000",
+            "endTimeUnixNano": "1637402400200000000",
+            "attributes": [
+                {"key": "test.case.name", "value": {"stringValue": "test_one"}},
+                {"key": "test.case.result.status", "value": {"stringValue": "pass"}},
+                {"key": "ctest.status", "value": {"stringValue": "Passed"}},
+                {"key": "ctest.lane", "value": {"intValue": "0"}},
+                {"key": "ctest.test.number", "value": {"intValue": "1"}},
+                {"key": "ctest.labels", "value": {"arrayValue": {"values": [{"stringValue": "db"}]}}}
+            ],
+            "status": {"code": 1}
+        });
+        assert_eq!(spans[1], expected);
+
+        let timeout = &spans[2];
+        assert_eq!(timeout["spanId"], "0000000000000003");
+        assert_eq!(timeout["startTimeUnixNano"], "1637402400100000000");
+        assert_eq!(timeout["attributes"][3]["value"]["intValue"], "1");
+        assert_eq!(timeout["status"], json!({"code": 2, "message": "Timeout"}));
+        assert_eq!(spans[3]["status"], json!({"code": 0}));
+        assert_eq!(spans[3]["attributes"][1]["value"]["stringValue"], "skip");
+    }
+
+    #[test]
+    fn test_root_span_covers_tests() {
+        let mut run = run();
+        run.summary = None;
+        run.traces[1].duration = Duration::from_secs(2);
+        let mut export = export();
+        export.run_id = None;
+        let json = to_json(&export_request(&run, &export));
+        let root = &json["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(root["endTimeUnixNano"], "1637402402100000000");
+        assert_eq!(root["attributes"].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_random_trace_ids() {
+        let id = TraceId::random().unwrap();
+        assert_ne!(id, TraceId([0; 16]));
+        assert_ne!(id, TraceId::random().unwrap());
+        assert_eq!(id.to_hex().len(), 32);
+    }
+
+    #[test]
+    fn test_write_otlp_json() {
+        let request = export_request(&run(), &export());
+        let mut output = vec![];
+        write_otlp_json(&mut output, &request).unwrap();
+        assert!(output.ends_with(b"}\n"));
+        let json: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json, to_json(&request));
+    }
+}
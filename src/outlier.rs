@@ -0,0 +1,367 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::history::RecordedRun;
+use crate::trace::{TestStatus, Trace};
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+
+/// The score above which a test is an outlier, as recommended by Iglewicz
+/// and Hoaglin for the modified z-score.
+pub const DEFAULT_THRESHOLD: f64 = 3.5;
+
+// The fewest baseline durations of a test to say what's usual for it
+const MIN_SAMPLES: usize = 3;
+
+// ctest prints durations to the hundredth of a second, so a test which
+// always took the same time still has this much spread
+const RESOLUTION: Duration = Duration::from_millis(10);
+
+// Scales the median absolute deviation to the standard deviation of a
+// normal distribution, so scores read like z-scores
+const MAD_SCALE: f64 = 0.6745;
+
+/// How much slower a test was than its baseline, when unusually slow.
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Outlier {
+    /// The modified z-score of the duration, the number of scaled median
+    /// absolute deviations it was above the baseline's median
+    pub score: f64,
+    /// The median duration of the test in the baseline
+    pub baseline_median: Duration,
+}
+
+/// The durations of tests in previous runs, which a new run is compared to.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Baseline {
+    durations: HashMap<String, Vec<Duration>>,
+}
+
+impl Baseline {
+    /// Adds a previous duration of the test `name`.
+    pub fn add(&mut self, name: &str, duration: Duration) {
+        self.durations
+            .entry(name.to_string())
+            .or_default()
+            .push(duration);
+    }
+
+    /// Adds the passing tests of `traces`, from a previous run.
+    ///
+    /// Failing tests are left out, as a test which crashed or timed out
+    /// doesn't show how long it usually takes.
+    pub fn add_traces(&mut self, traces: &[Trace]) {
+        for trace in traces.iter().filter(|t| t.status == TestStatus::Passed) {
+            self.add(&trace.name, trace.duration);
+        }
+    }
+
+    /// Adds the passing tests of the last `last` runs of a timing history.
+    pub fn add_history(&mut self, runs: &[RecordedRun], last: usize) {
+        let skip = runs.len().saturating_sub(last);
+        for run in runs.iter().skip(skip) {
+            for test in run
+                .tests
+                .iter()
+                .filter(|t| t.status() == TestStatus::Passed)
+            {
+                self.add(&test.name, test.duration());
+            }
+        }
+    }
+
+    /// Scores `trace` against the previous durations of its test, if it was
+    /// unusually slow.
+    ///
+    /// The score is the modified z-score, which uses the median and median
+    /// absolute deviation so a few slow runs in the baseline don't hide the
+    /// next one.  Tests with fewer than 3 previous durations aren't scored.
+    pub fn outlier(&self, trace: &Trace, threshold: f64) -> Option<Outlier> {
+        let durations = self.durations.get(&trace.name)?;
+        if durations.len() < MIN_SAMPLES {
+            return None;
+        }
+        let usual = median(durations.clone());
+        let deviations = durations.iter().map(|d| d.abs_diff(usual)).collect();
+        let spread = median(deviations).max(RESOLUTION);
+
+        let above = trace.duration.as_secs_f64() - usual.as_secs_f64();
+        let score = MAD_SCALE * above / spread.as_secs_f64();
+        if score > threshold {
+            Some(Outlier {
+                score,
+                baseline_median: usual,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Sets the [`outlier`](Trace::outlier) of each of `traces` which was
+    /// unusually slow, returning the number of outliers.
+    pub fn mark_outliers(&self, traces: &mut [Trace], threshold: f64) -> usize {
+        let mut count = 0;
+        for trace in traces {
+            trace.outlier = self.outlier(trace, threshold);
+            count += trace.outlier.iter().count();
+        }
+        count
+    }
+}
+
+fn median(mut durations: Vec<Duration>) -> Duration {
+    durations.sort();
+    match durations.len() {
+        0 => Duration::new(0, 0),
+        n if n % 2 == 1 => durations[n / 2],
+        n => (durations[n / 2 - 1] + durations[n / 2]) / 2,
+    }
+}
+
+// The outliers among `traces`, the highest scoring first
+fn outliers(traces: &[Trace]) -> Vec<(&Trace, &Outlier)> {
+    let mut outliers: Vec<(&Trace, &Outlier)> = traces
+        .iter()
+        .filter_map(|t| t.outlier.as_ref().map(|o| (t, o)))
+        .collect();
+    outliers.sort_by(|a, b| b.1.score.total_cmp(&a.1.score));
+    outliers
+}
+
+/// Writes a warning line for each of `traces` which is an outlier, in the
+/// order they ran, i.e. to stderr.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_outlier_warnings<W: Write>(mut writer: W, traces: &[Trace]) -> io::Result<()> {
+    for trace in traces {
+        if let Some(outlier) = &trace.outlier {
+            writeln!(
+                writer,
+                "warning: \"{}\" took {:.3} sec, its baseline median is {:.3} sec (outlier score {:.1})",
+                trace.name,
+                trace.duration.as_secs_f64(),
+                outlier.baseline_median.as_secs_f64(),
+                outlier.score
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a table of the outliers among `traces`, marked by
+/// [`Baseline::mark_outliers()`](Baseline::mark_outliers), the highest
+/// scoring first.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_outliers_text<W: Write>(mut writer: W, traces: &[Trace]) -> io::Result<()> {
+    //  A row for each outlier:
+    //
+    //          Duration     Baseline   Score  Test
+    //         1.500 sec    1.000 sec    33.7  test_two
+    let outliers = outliers(traces);
+    if outliers.is_empty() {
+        return writeln!(writer, "No outliers in {} tests", traces.len());
+    }
+    writeln!(writer, "    Duration     Baseline   Score  Test")?;
+    for (trace, outlier) in outliers {
+        writeln!(
+            writer,
+            "{:>8.3} sec {:>8.3} sec {:>7.1}  {}",
+            trace.duration.as_secs_f64(),
+            outlier.baseline_median.as_secs_f64(),
+            outlier.score,
+            trace.name
+        )?;
+    }
+    Ok(())
+}
+
+// An outlier as written by `write_outliers_json()`
+#[derive(Serialize)]
+struct OutlierReport<'a> {
+    name: &'a str,
+    seconds: f64,
+    baseline_median_seconds: f64,
+    score: f64,
+}
+
+/// Writes the outliers among `traces`, as
+/// [`write_outliers_text()`](write_outliers_text), as a JSON array on a
+/// single line.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_outliers_json<W: Write>(mut writer: W, traces: &[Trace]) -> io::Result<()> {
+    //  An object for each outlier:
+    //
+    //      [{"name":"test_two","seconds":1.5,"baseline_median_seconds":1.0,
+    //        "score":33.725}]
+    let report: Vec<OutlierReport> = outliers(traces)
+        .into_iter()
+        .map(|(trace, outlier)| OutlierReport {
+            name: &trace.name,
+            seconds: trace.duration.as_secs_f64(),
+            baseline_median_seconds: outlier.baseline_median.as_secs_f64(),
+            score: outlier.score,
+        })
+        .collect();
+    serde_json::to_writer(&mut writer, &report)?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::RecordedTest;
+
+    fn test(name: &str, millis: u64) -> Trace {
+        Trace::new(name, Duration::new(0, 0), Duration::from_millis(millis))
+    }
+
+    fn baseline(millis: &[u64]) -> Baseline {
+        let mut baseline = Baseline::default();
+        for m in millis {
+            baseline.add("a", Duration::from_millis(*m));
+        }
+        baseline
+    }
+
+    #[test]
+    fn test_slow_test_is_outlier() {
+        let baseline = baseline(&[1000, 1100, 900, 1000, 3000]);
+        let outlier = baseline
+            .outlier(&test("a", 2000), DEFAULT_THRESHOLD)
+            .unwrap();
+        // The deviations are 0, 100, 100, 0, and 2000 ms
+        assert!((outlier.score - 6.745).abs() < 1e-9);
+        assert_eq!(outlier.baseline_median, Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_usual_durations_are_not_outliers() {
+        let baseline = baseline(&[1000, 1100, 900, 1000, 3000]);
+        assert_eq!(baseline.outlier(&test("a", 1200), DEFAULT_THRESHOLD), None);
+        assert_eq!(baseline.outlier(&test("a", 100), DEFAULT_THRESHOLD), None);
+    }
+
+    #[test]
+    fn test_identical_durations_use_resolution() {
+        let baseline = baseline(&[500, 500, 500]);
+        assert_eq!(baseline.outlier(&test("a", 510), DEFAULT_THRESHOLD), None);
+        assert_eq!(baseline.outlier(&test("a", 550), DEFAULT_THRESHOLD), None);
+        assert!(baseline
+            .outlier(&test("a", 600), DEFAULT_THRESHOLD)
+            .is_some());
+    }
+
+    #[test]
+    fn test_too_few_samples() {
+        let baseline = baseline(&[100, 100]);
+        assert_eq!(baseline.outlier(&test("a", 10000), DEFAULT_THRESHOLD), None);
+        assert_eq!(baseline.outlier(&test("b", 10000), DEFAULT_THRESHOLD), None);
+    }
+
+    #[test]
+    fn test_failures_left_out_of_baseline() {
+        let timeout = test("a", 60000).with_status(TestStatus::Timeout);
+        let mut baseline = Baseline::default();
+        baseline.add_traces(&[test("a", 100), test("a", 100), timeout]);
+        assert_eq!(baseline.outlier(&test("a", 10000), DEFAULT_THRESHOLD), None);
+
+        baseline.add_traces(&[test("a", 100)]);
+        assert!(baseline
+            .outlier(&test("a", 10000), DEFAULT_THRESHOLD)
+            .is_some());
+    }
+
+    #[test]
+    fn test_baseline_from_history() {
+        let run = |seconds: f64, status: &str| RecordedRun {
+            tests: vec![RecordedTest {
+                name: "a".into(),
+                seconds,
+                status: status.into(),
+            }],
+            ..Default::default()
+        };
+        let runs = vec![
+            run(9.0, "Passed"),
+            run(1.0, "Passed"),
+            run(1.0, "Failed"),
+            run(1.0, "Passed"),
+            run(1.0, "Passed"),
+        ];
+        let mut baseline = Baseline::default();
+        baseline.add_history(&runs, 4);
+        assert_eq!(baseline, self::baseline(&[1000, 1000, 1000]));
+    }
+
+    #[test]
+    fn test_mark_outliers() {
+        let baseline = baseline(&[100, 100, 100]);
+        let mut traces = vec![test("a", 100), test("a", 5000), test("b", 5000)];
+        assert_eq!(baseline.mark_outliers(&mut traces, DEFAULT_THRESHOLD), 1);
+        assert_eq!(traces[0].outlier, None);
+        assert!(traces[1].outlier.is_some());
+        assert_eq!(traces[2].outlier, None);
+    }
+
+    fn marked() -> Vec<Trace> {
+        let outlier = |score, millis| Outlier {
+            score,
+            baseline_median: Duration::from_millis(millis),
+        };
+        let mut traces = vec![test("a", 3000), test("b", 100), test("c", 1500)];
+        traces[0].outlier = Some(outlier(6.745, 1000));
+        traces[2].outlier = Some(outlier(33.725, 1000));
+        traces
+    }
+
+    #[test]
+    fn test_write_outlier_warnings() {
+        let mut output = vec![];
+        write_outlier_warnings(&mut output, &marked()).unwrap();
+        let expected = r#"warning: "a" took 3.000 sec, its baseline median is 1.000 sec (outlier score 6.7)
+warning: "c" took 1.500 sec, its baseline median is 1.000 sec (outlier score 33.7)
+"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_outliers_text() {
+        let mut output = vec![];
+        write_outliers_text(&mut output, &marked()).unwrap();
+        let expected = "    Duration     Baseline   Score  Test
+   1.500 sec    1.000 sec    33.7  c
+   3.000 sec    1.000 sec     6.7  a
+";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        let mut output = vec![];
+        write_outliers_text(&mut output, &[test("b", 100)]).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "No outliers in 1 tests\n"
+        );
+    }
+
+    #[test]
+    fn test_write_outliers_json() {
+        let mut output = vec![];
+        write_outliers_json(&mut output, &marked()).unwrap();
+        let expected = r#"[{"name":"c","seconds":1.5,"baseline_median_seconds":1.0,"score":33.725},{"name":"a","seconds":3.0,"baseline_median_seconds":1.0,"score":6.745}]
+"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
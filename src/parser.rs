@@ -3,120 +3,753 @@
 //    (See accompanying file LICENSE or copy at
 //          https://www.boost.org/LICENSE_1_0.txt)
 
-use crate::trace::Trace;
+use crate::normalize::Normalizer;
+use crate::resources::ResourceAllocation;
+use crate::test_info::TestInfo;
+use crate::trace::{TestStatus, Trace};
 
-use nom::bytes::complete::{tag, take_till, take_while1};
-use nom::character::complete::{char, digit1, space1};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till, take_till1, take_while1, take_while_m_n};
+use nom::character::complete::{char, digit1, one_of, space0, space1};
 use nom::character::is_digit;
-use nom::sequence::tuple;
+use nom::combinator::{all_consuming, map_res, opt};
+use nom::multi::separated_list1;
+use nom::sequence::{preceded, terminated, tuple};
 use nom::IResult;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::{HashMap, VecDeque};
 use std::error::Error;
 use std::fmt;
+use std::io;
 use std::io::{BufRead, BufReader, Read};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone, PartialEq)]
-struct ParseError {
-    message: String,
+/// A problem with the ctest output, or with reading it.
+///
+/// Each variant has the 1 based number of the line with the problem, and the
+/// content of that line.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// A test finished which was never started
+    OrphanFinish {
+        line: usize,
+        text: String,
+        name: String,
+    },
+    /// A test started again while it was still running
+    DuplicateStart {
+        line: usize,
+        text: String,
+        name: String,
+    },
+    /// Reading the line failed, `text` is the part of the line read before
+    /// the failure
+    Io {
+        line: usize,
+        text: String,
+        source: io::Error,
+    },
+    /// The line is ctest output, but isn't valid UTF-8.  `text` has the
+    /// invalid bytes replaced.
+    InvalidEncoding { line: usize, text: String },
 }
 
 impl ParseError {
-    fn new(msg: &str) -> ParseError {
-        ParseError {
-            message: msg.to_string(),
+    /// The 1 based number of the line with the problem.
+    pub fn line(&self) -> usize {
+        match self {
+            ParseError::OrphanFinish { line, .. }
+            | ParseError::DuplicateStart { line, .. }
+            | ParseError::Io { line, .. }
+            | ParseError::InvalidEncoding { line, .. } => *line,
+        }
+    }
+
+    /// The content of the line with the problem.
+    pub fn text(&self) -> &str {
+        match self {
+            ParseError::OrphanFinish { text, .. }
+            | ParseError::DuplicateStart { text, .. }
+            | ParseError::Io { text, .. }
+            | ParseError::InvalidEncoding { text, .. } => text,
         }
     }
+
+    /// The error with the line it's about, as a source snippet:
+    ///
+    /// ```text
+    /// error: Saw end of "test_one" without start indicator
+    ///  --> line 3
+    ///   |
+    /// 3 |             1/2 Test #1: test_one ......   Passed   0.20 sec
+    ///   |
+    /// ```
+    pub fn snippet(&self) -> String {
+        let number = self.line().to_string();
+        let gutter = " ".repeat(number.len());
+        format!(
+            "error: {}\n{}--> line {}\n{} |\n{} | {}\n{} |",
+            self,
+            gutter,
+            number,
+            gutter,
+            number,
+            self.text(),
+            gutter
+        )
+    }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.message)
+        match self {
+            ParseError::OrphanFinish { name, .. } => {
+                write!(f, "Saw end of \"{}\" without start indicator", name)
+            }
+            ParseError::DuplicateStart { name, .. } => {
+                write!(f, "Saw start of \"{}\" while it was already running", name)
+            }
+            ParseError::Io { source, .. } => {
+                write!(f, "Failed to read the ctest output: {}", source)
+            }
+            ParseError::InvalidEncoding { .. } => write!(f, "ctest output is not valid UTF-8"),
+        }
     }
 }
 
 impl Error for ParseError {
-    fn description(&self) -> &str {
-        &self.message
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Options controlling how ctest output is parsed.
+#[derive(Debug, Default, Clone)]
+pub struct ParseOptions {
+    /// The properties of the tests, used for the number of threads each test
+    /// holds and its labels.
+    pub test_info: TestInfo,
+    /// When set, problems with the ctest output are recorded as
+    /// [`Diagnostic`](Diagnostic)s and parsing carries on, instead of failing.
+    pub lenient: bool,
+    /// Cleans up each line before it is parsed
+    pub normalizer: Normalizer,
+}
+
+/// A problem with the ctest output which was skipped over when parsing
+/// leniently.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// The 1 based line number of the problem
+    pub line: usize,
+    /// The content of the line
+    pub text: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}\n    {}", self.line, self.message, self.text)
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Diagnostic {
+        Diagnostic {
+            line: error.line(),
+            text: error.text().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// The result of parsing the output of a ctest run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Run {
+    /// The tests which ran, in the order they finished
+    pub traces: Vec<Trace>,
+    /// The problems skipped over, only populated when parsing leniently
+    pub diagnostics: Vec<Diagnostic>,
+    /// When the first test started, only known when the lines of the output
+    /// have timestamps
+    pub start_time: Option<SystemTime>,
+    /// The totals ctest reported at the end of the run, if it finished
+    pub summary: Option<Summary>,
+}
+
+impl Run {
+    /// The wall clock time of the run, as ctest reported it, or else from the
+    /// first test starting to the last one finishing.
+    pub fn wall_time(&self) -> Duration {
+        if let Some(summary) = &self.summary {
+            return summary.total_time;
+        }
+        let start = self.traces.iter().map(|t| t.start).min();
+        let end = self.traces.iter().map(|t| t.start + t.duration).max();
+        match (start, end) {
+            (Some(start), Some(end)) => end - start,
+            _ => Duration::new(0, 0),
+        }
+    }
+}
+
+/// The totals ctest reports at the end of a run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Summary {
+    /// The number of tests ctest ran, or tried to run
+    pub total: u32,
+    pub failed: u32,
+    /// The wall clock time of the whole run, as measured by ctest
+    pub total_time: Duration,
+}
+
+/// What a line of ctest output meant, as reported by
+/// [`Parser::push_line`](Parser::push_line).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseEvent {
+    TestStarted {
+        name: String,
+        number: u32,
+        start: Duration,
+        thread_number: u32,
+        /// The other threads held by a test which uses more than one
+        /// processor, as in [`Trace::extra_threads`](Trace::extra_threads)
+        extra_threads: Vec<u32>,
+    },
+    TestFinished(Trace),
+    Summary(Summary),
+}
+
+// A test which has started but not yet finished
+struct RunningTest {
+    number: u32,
+    start: Duration,
+    // Whether `start` is from a timestamp rather than inferred
+    observed: bool,
+    threads: Vec<u32>,
+    resources: Vec<ResourceAllocation>,
+}
+
+/// Parses ctest output one line at a time, reporting tests as they start and
+/// finish.
+///
+/// This allows reacting to tests as a ctest run progresses, where
+/// [`parse`](parse) only reports once all of the output has been read.
+pub struct Parser {
+    options: ParseOptions,
+    running_tests: HashMap<String, RunningTest>,
+    diagnostics: Vec<Diagnostic>,
+    trace_timer: Duration,
+    start_time: Option<Duration>,
+    max_thread_number: u32,
+    free_threads: VecDeque<u32>,
+    line_number: usize,
+    // The counts from the "tests passed" line, waiting on the total time
+    // which follows it
+    summary: Summary,
+}
+
+impl Parser {
+    /// Creates a parser for the start of some ctest output.
+    pub fn new(options: ParseOptions) -> Parser {
+        Parser {
+            options,
+            running_tests: HashMap::new(),
+            diagnostics: vec![],
+            trace_timer: Duration::new(0, 0),
+            start_time: None,
+            max_thread_number: 0,
+            free_threads: VecDeque::new(),
+            line_number: 0,
+            summary: Summary::default(),
+        }
+    }
+
+    /// Parses the next line of ctest output, without the line ending.
+    ///
+    /// Returns the [`ParseEvent`](ParseEvent) for the line, or `None` for
+    /// lines which aren't ctest output or don't complete anything.  See
+    /// [`parse_with_options`](parse_with_options) for how the
+    /// [`ParseOptions`](ParseOptions) affect parsing.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError`](ParseError) when there is an end of a test but
+    /// the start of said test was never seen, or a test starts again while
+    /// it's still running, unless parsing leniently.
+    pub fn push_line(&mut self, line: &str) -> Result<Option<ParseEvent>, ParseError> {
+        self.line_number += 1;
+        let (content, observed) = match parse_timestamp_prefix(line) {
+            Ok((rest, timestamp)) => (rest, Some(timestamp)),
+            Err(_) => (line, None),
+        };
+        let content = self.options.normalizer.normalize(content);
+        let content = content.as_ref();
+        if let Ok((_, (number, test_case))) = parse_test_start(content) {
+            if let Some(test) = self.running_tests.remove(&test_case) {
+                self.report(ParseError::DuplicateStart {
+                    line: self.line_number,
+                    text: line.to_string(),
+                    name: test_case.clone(),
+                })?;
+                // Leniently, the test starts over
+                self.free_threads.extend(test.threads);
+            }
+            return Ok(Some(self.start_test(number, test_case, observed)));
+        }
+        if let Ok((_, (number, mut allocations))) = parse_resource_group(content) {
+            if let Some(test) = self.running_tests.values_mut().find(|t| t.number == number) {
+                test.resources.append(&mut allocations);
+            }
+            return Ok(None);
+        }
+        if let Ok((_, (test_case, status, duration))) = parse_test_finish(content) {
+            // When a test is not run it will output the same as a finish message, but won't
+            // have a start message, so won't exist in running_tests
+            if let Some(test) = self.running_tests.remove(&test_case) {
+                // ctest rounds the duration to hundredths of a second, so when both ends of
+                // the test have timestamps the end of the trace is when it finished
+                let duration = match (observed, self.start_time) {
+                    (Some(timestamp), Some(origin)) if test.observed => {
+                        timestamp.saturating_sub(origin).saturating_sub(test.start)
+                    }
+                    _ => duration,
+                };
+                let labels = self.options.test_info.labels(&test_case).to_vec();
+                let trace = Trace {
+                    name: test_case,
+                    number: Some(test.number),
+                    start: test.start,
+                    duration,
+                    thread_number: test.threads[0],
+                    extra_threads: test.threads[1..].to_vec(),
+                    resources: test.resources,
+                    status,
+                    labels,
+                    outlier: None,
+                };
+                self.trace_timer = test.start + duration;
+                self.free_threads.extend(test.threads);
+                return Ok(Some(ParseEvent::TestFinished(trace)));
+            } else if !duration.is_zero() {
+                self.report(ParseError::OrphanFinish {
+                    line: self.line_number,
+                    text: line.to_string(),
+                    name: test_case,
+                })?;
+            }
+            return Ok(None);
+        }
+        if let Ok((_, (total, failed))) = parse_tests_passed(content) {
+            self.summary.total = total;
+            self.summary.failed = failed;
+            return Ok(None);
+        }
+        if let Ok((_, total_time)) = parse_total_time(content) {
+            self.summary.total_time = total_time;
+            return Ok(Some(ParseEvent::Summary(self.summary.clone())));
+        }
+        Ok(None)
+    }
+
+    /// The problems skipped over so far, only populated when parsing
+    /// leniently.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// When the first test started, only known when the lines of the output
+    /// have timestamps.
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.start_time.map(|t| UNIX_EPOCH + t)
+    }
+
+    // Fails with `error`, or when parsing leniently records it to carry on
+    fn report(&mut self, error: ParseError) -> Result<(), ParseError> {
+        if !self.options.lenient {
+            return Err(error);
+        }
+        self.diagnostics.push(Diagnostic::from(&error));
+        Ok(())
+    }
+
+    fn start_test(&mut self, number: u32, name: String, observed: Option<Duration>) -> ParseEvent {
+        let processors = self.options.test_info.processors(&name);
+        let mut threads = vec![];
+        for _ in 0..processors {
+            let thread = match self.free_threads.pop_front() {
+                Some(number) => number,
+                None => {
+                    let number = self.max_thread_number;
+                    self.max_thread_number += 1;
+                    number
+                }
+            };
+            threads.push(thread);
+        }
+        let start = match observed {
+            Some(timestamp) => {
+                let origin = *self.start_time.get_or_insert(timestamp);
+                timestamp.saturating_sub(origin)
+            }
+            None => self.trace_timer,
+        };
+        let event = ParseEvent::TestStarted {
+            name: name.clone(),
+            number,
+            start,
+            thread_number: threads[0],
+            extra_threads: threads[1..].to_vec(),
+        };
+        let test = RunningTest {
+            number,
+            start,
+            observed: observed.is_some(),
+            threads,
+            resources: vec![],
+        };
+        self.running_tests.insert(name, test);
+        event
+    }
+}
+
+/// An [`Iterator`](Iterator) over the [`ParseEvent`](ParseEvent)s of the ctest
+/// output read from a [`BufRead`](BufRead).
+///
+/// Lines are read as they're needed, so this can follow output which is still
+/// being written, i.e. piped from ctest.
+pub struct Events<R: BufRead> {
+    reader: R,
+    parser: Parser,
+    failed: bool,
+}
+
+impl<R: BufRead> Events<R> {
+    /// Creates an iterator over the events in `reader`.
+    pub fn new(reader: R, options: ParseOptions) -> Events<R> {
+        Events {
+            reader,
+            parser: Parser::new(options),
+            failed: false,
+        }
+    }
+
+    /// The parser, i.e. for the diagnostics once iteration is done.
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+}
+
+impl<R: BufRead> Iterator for Events<R> {
+    type Item = Result<ParseEvent, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let mut bytes = vec![];
+        loop {
+            bytes.clear();
+            match self.reader.read_until(b'\n', &mut bytes) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(source) => {
+                    self.failed = true;
+                    return Some(Err(ParseError::Io {
+                        line: self.parser.line_number + 1,
+                        text: String::from_utf8_lossy(&bytes).into_owned(),
+                        source,
+                    }));
+                }
+            }
+            if bytes.last() == Some(&b'\n') {
+                bytes.pop();
+            }
+            // Lines are split on the raw bytes and decoded lossily, so output which isn't
+            // valid UTF-8, i.e. a test printing binary data, is ignored like any other
+            // non ctest output.  Only when strict is ctest output itself being invalid an
+            // error.
+            let line = String::from_utf8_lossy(&bytes);
+            let invalid = matches!(line, Cow::Owned(_));
+            match self.parser.push_line(&line) {
+                Ok(Some(_)) if invalid && !self.parser.options.lenient => {
+                    self.failed = true;
+                    return Some(Err(ParseError::InvalidEncoding {
+                        line: self.parser.line_number,
+                        text: line.into_owned(),
+                    }));
+                }
+                Ok(Some(event)) => return Some(Ok(event)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
     }
 }
 
 /// Parses result output of ctest results and turns into a [`Trace`](Trace)s.
 /// Each [`Trace`](Trace) represents an individual test.
 ///
-/// Will ignore and advance past output that is not ctest related.
+/// Will ignore and advance past output that is not ctest related, including
+/// output which is not valid UTF-8.
 ///
 /// # Errors
 ///
-/// Returns [`ParseError`](ParseError) when there is an end of ctest but the
-/// start of said test was never seen.
+/// Returns [`ParseError::OrphanFinish`](ParseError::OrphanFinish) when there
+/// is an end of a test but the start of said test was never seen, and
+/// [`ParseError::DuplicateStart`](ParseError::DuplicateStart) when a test
+/// starts again while it's still running.
+///
+/// Returns [`ParseError::InvalidEncoding`](ParseError::InvalidEncoding) when
+/// a line of ctest output is not valid UTF-8.
+///
+/// Returns [`ParseError::Io`](ParseError::Io) when there is a failure to read
+/// the lines of the provided `reader`.
+///
+pub fn parse<R: Read>(reader: BufReader<R>) -> Result<Vec<Trace>, ParseError> {
+    Ok(parse_with_options(reader, &ParseOptions::default())?.traces)
+}
+
+/// Parses result output of ctest results, as [`parse`](parse), using the
+/// provided `options`.
+///
+/// Tests with a `PROCESSORS` property, in `options.test_info`, will hold that
+/// many threads while running.
 ///
-/// Returns [`io::Error`](io::Error) when there is a failure to read the lines
-/// of the provided `reader`.
+/// When ctest is run with `-V` and a resource spec file, the resources given
+/// to each test are collected into [`Trace::resources`](Trace::resources).
 ///
-pub fn parse<R: Read>(reader: BufReader<R>) -> Result<Vec<Trace>, Box<dyn std::error::Error>> {
-    let mut running_tests: HashMap<String, (Duration, u32)> = HashMap::new();
+/// Lines prefixed with an ISO 8601 timestamp, as in GitHub Actions and GitLab
+/// logs, use the timestamp as the start of the test instead of inferring it
+/// from when earlier tests finished, and the timestamp of its finish as the
+/// end, as ctest rounds the duration.  After the timestamp, each line is
+/// cleaned up with `options.normalizer`.
+///
+/// # Errors
+///
+/// See [`parse`](parse).  When `options.lenient` is set, only failures to
+/// read the `reader` are errors, the other problems are recorded in
+/// [`Run::diagnostics`](Run::diagnostics), and lines which aren't valid UTF-8
+/// are parsed with the invalid bytes replaced.
+pub fn parse_with_options<R: Read>(
+    reader: BufReader<R>,
+    options: &ParseOptions,
+) -> Result<Run, ParseError> {
+    let mut events = Events::new(reader, options.clone());
     let mut traces = vec![];
-    let mut trace_timer = Duration::new(0, 0);
-    let mut max_thread_number = 0;
-    let mut free_threads = VecDeque::new();
-    for l in reader.lines() {
-        let line = l?;
-        if let Ok((_, test_case)) = parse_test_start(&line) {
-            let thread_number = match free_threads.pop_front() {
-                Some(number) => number,
-                None => {
-                    let number = max_thread_number;
-                    max_thread_number += 1;
-                    number
-                }
-            };
-            running_tests.insert(test_case, (trace_timer, thread_number));
-            continue;
-        }
-        if let Ok((_, (test_case, duration))) = parse_test_finish(&line) {
-            // When a test is not run it will output the same as a finish message, but won't
-            // have a start message, so won't exist in running_tests
-            if let Some((start, thread)) = running_tests.remove(&test_case) {
-                traces.push(Trace {
-                    name: test_case,
-                    start,
-                    duration,
-                    thread_number: thread,
-                });
-                trace_timer = start + duration;
-                free_threads.push_back(thread);
-            } else if !duration.is_zero() {
-                let message = format!("Saw end of \"{}\" without start indicator", test_case);
-                return Err(ParseError::new(&message).into());
-            }
-            continue;
+    let mut summary = None;
+    for event in &mut events {
+        match event? {
+            ParseEvent::TestFinished(trace) => traces.push(trace),
+            ParseEvent::Summary(s) => summary = Some(s),
+            ParseEvent::TestStarted { .. } => {}
         }
     }
-    Ok(traces)
+    let parser = events.parser();
+    Ok(Run {
+        traces,
+        diagnostics: parser.diagnostics().to_vec(),
+        start_time: parser.start_time(),
+        summary,
+    })
+}
+
+//  Parse the line summarizing the results of the tests.
+//  Returns the total number of tests and the number which failed
+//  Expected format is:
+//
+//      100% tests passed, 0 tests failed out of 24
+//  or
+//      96% tests passed, 1 test failed out of 24
+//
+fn parse_tests_passed(i: &str) -> IResult<&str, (u32, u32)> {
+    let count = || map_res(digit1, str::parse);
+    let failed_out_of = alt((tag(" tests failed out of "), tag(" test failed out of ")));
+
+    let (input, (_, _, _, _, failed, _, total)) = tuple((
+        space0,
+        digit1,
+        tag("% tests passed, "),
+        space0,
+        count(),
+        failed_out_of,
+        count(),
+    ))(i)?;
+
+    Ok((input, (total, failed)))
+}
+
+//  Parse the line with the time the whole run took.
+//  Returns the duration of the run
+//  Expected format is:
+//
+//      Total Test time (real) =   4.85 sec
+//
+fn parse_total_time(i: &str) -> IResult<&str, Duration> {
+    let (input, (_, _, _, seconds_str, _, fraction_str, _, _)) = tuple((
+        space0,
+        tag("Total Test time (real) ="),
+        space1,
+        digit1,
+        char('.'),
+        digit1,
+        space1,
+        tag("sec"),
+    ))(i)?;
+
+    // Using unwrap as the digits were parsed above
+    let seconds = seconds_str.parse().unwrap();
+    Ok((input, Duration::new(seconds, parse_nanos(fraction_str))))
+}
+
+//  Parse the timestamp prefix CI systems put on each line of their logs.
+//  Returns the time since the Unix epoch
+//  Expected format is:
+//
+//      2021-11-20T10:00:00.1234567Z     Start 30: name_of_test
+//  or, for GitLab with its stream marker
+//      2021-11-20T10:00:00.123456Z 00O     Start 30: name_of_test
+//
+fn parse_timestamp_prefix(i: &str) -> IResult<&str, Duration> {
+    let fraction = opt(preceded(char('.'), digit1));
+    let gitlab_marker = opt(terminated(
+        tuple((
+            take_while_m_n(2, 2, |c: char| c.is_ascii_hexdigit()),
+            one_of("OE"),
+            opt(char('+')),
+        )),
+        char(' '),
+    ));
+
+    let (input, (year, _, month, _, day, _, hour, _, minute, _, second, fraction, _, _, _)) =
+        tuple((
+            fixed_digits(4),
+            char('-'),
+            fixed_digits(2),
+            char('-'),
+            fixed_digits(2),
+            char('T'),
+            fixed_digits(2),
+            char(':'),
+            fixed_digits(2),
+            char(':'),
+            fixed_digits(2),
+            fraction,
+            char('Z'),
+            char(' '),
+            gitlab_marker,
+        ))(i)?;
+
+    let days = days_since_epoch(year, month, day);
+    let seconds = ((days * 24 + hour) * 60 + minute) * 60 + second;
+    let nanos = fraction.map_or(0, parse_nanos);
+    Ok((input, Duration::new(seconds, nanos)))
+}
+
+// A parser for exactly `count` digits
+fn fixed_digits(count: usize) -> impl FnMut(&str) -> IResult<&str, u64> {
+    move |i| {
+        map_res(
+            take_while_m_n(count, count, |c: char| c.is_ascii_digit()),
+            str::parse,
+        )(i)
+    }
+}
+
+// The nanoseconds represented by the digits after a decimal point, digits past
+// nanosecond precision are dropped.
+fn parse_nanos(digits: &str) -> u32 {
+    let digits = &digits[..digits.len().min(9)];
+    let scale = 10u32.pow(9 - digits.len() as u32);
+    // Using unwrap as the caller has ensured these are digits, and 9 digits
+    // will always fit.
+    digits.parse::<u32>().unwrap() * scale
+}
+
+// The number of days from 1970-01-01 to the provided civil date, see
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil.
+// Dates before 1970 saturate to the epoch.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 {
+        year.saturating_sub(1)
+    } else {
+        year
+    };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146097 + day_of_era).saturating_sub(719468)
 }
 
 //  Parse a line that indicates the start of a test.
-//  Returns the name of the test that just started
+//  Returns the number and name of the test that just started
 //  Expected format is:
 //
 //      Start 30: name_of_test
 //
-fn parse_test_start(i: &str) -> IResult<&str, String> {
+fn parse_test_start(i: &str) -> IResult<&str, (u32, String)> {
     let space = space1;
     let test_name = take_while1(|c| c != ' ');
-    let test_number = digit1;
+    let test_number = map_res(digit1, str::parse);
     let start = tag("Start");
     let colon = char(':');
 
-    let (input, (_, _, _, _, _, _, test_name)) =
+    let (input, (_, _, _, test_number, _, _, test_name)) =
         tuple((space, start, space, test_number, colon, space, test_name))(i)?;
 
-    Ok((input, test_name.into()))
+    Ok((input, (test_number, test_name.into())))
+}
+
+//  Parse a line of `ctest -V` output showing the resources given to a test.
+//  Returns the number of the test and the resources
+//  Expected format is:
+//
+//      3:  CTEST_RESOURCE_GROUP_0_GPUS=id:0,slots:1;id:1,slots:2
+//
+fn parse_resource_group(i: &str) -> IResult<&str, (u32, Vec<ResourceAllocation>)> {
+    let test_number = map_res(digit1, str::parse);
+    let resource_type = take_till1(|c| c == '=');
+    let id = take_till1(|c| c == ',');
+    let slots = map_res(digit1, str::parse);
+    let allocation = tuple((preceded(tag("id:"), id), preceded(tag(",slots:"), slots)));
+    let allocations = separated_list1(char(';'), allocation);
+
+    let (input, (_, test_number, _, _, _, _, _, resource_type, _, allocations)) =
+        all_consuming(tuple((
+            space0,
+            test_number,
+            char(':'),
+            space1,
+            tag("CTEST_RESOURCE_GROUP_"),
+            digit1,
+            char('_'),
+            resource_type,
+            char('='),
+            allocations,
+        )))(i.trim_end())?;
+
+    let allocations = allocations
+        .into_iter()
+        .map(|(id, slots)| ResourceAllocation {
+            resource_type: resource_type.to_lowercase(),
+            id: id.into(),
+            slots,
+        })
+        .collect();
+    Ok((input, (test_number, allocations)))
 }
 
 //  Parse a line that indicates a test has finished
-//  Returns the name of the test and the duration
+//  Returns the name of the test, how it finished, and the duration
 //  Expected format is:
 //
 //      1/1 Test #1: test_stuff .......................***Failed    0.81 sec
@@ -125,13 +758,13 @@ fn parse_test_start(i: &str) -> IResult<&str, String> {
 //  or
 //      1/1 Test #1: test_stuff ......................***Not Run   0.00 sec
 //
-fn parse_test_finish(i: &str) -> IResult<&str, (String, Duration)> {
+fn parse_test_finish(i: &str) -> IResult<&str, (String, TestStatus, Duration)> {
     let test_name = take_while1(|c| c != ' ');
     let colon = char(':');
     let test_result = take_till(|c| is_digit(c as u8));
     let test_number = take_till(|c| c == ':');
 
-    let (input, (_, _, _, test_name, _, seconds_str, _, centis_str, _, _)) = tuple((
+    let (input, (_, _, _, test_name, test_result, seconds_str, _, fraction_str, _, _)) = tuple((
         test_number,
         colon,
         space1,
@@ -148,11 +781,12 @@ fn parse_test_finish(i: &str) -> IResult<&str, (String, Duration)> {
     // some precision, i.e. 3.32 seconds will turn into 3.319 in the duration
     // Using unwrap here as it seems unlikely that the numbers were parsed out above, while not
     // being able to be parsed into a u64.
+    // The fraction is usually 2 digits, but some builds and wrappers print more or fewer, so it's
+    // parsed as an exact decimal fraction.
     let seconds = seconds_str.parse().unwrap();
-    let centis: u64 = centis_str.parse().unwrap();
-    let millis = centis * 10;
-    let duration = Duration::new(seconds, 0) + Duration::from_millis(millis);
-    Ok((input, (test_name.into(), duration)))
+    let duration = Duration::new(seconds, parse_nanos(fraction_str));
+    let status = TestStatus::from_result(test_result);
+    Ok((input, (test_name.into(), status, duration)))
 }
 
 #[cfg(test)]
@@ -165,7 +799,7 @@ mod tests {
 
         assert_eq!(
             parse_test_start(ctest_output),
-            Ok(("", "start_of_a_test".into()))
+            Ok(("", (1, "start_of_a_test".into())))
         );
     }
 
@@ -175,7 +809,91 @@ mod tests {
 
         assert_eq!(
             parse_test_start(ctest_output),
-            Ok(("", "a_different_test".into()))
+            Ok(("", (30, "a_different_test".into())))
+        );
+    }
+
+    #[test]
+    fn test_parse_resource_group() {
+        let ctest_output = "3:  CTEST_RESOURCE_GROUP_0_GPUS=id:0,slots:1;id:card1,slots:2";
+
+        let allocations = vec![
+            ResourceAllocation {
+                resource_type: "gpus".into(),
+                id: "0".into(),
+                slots: 1,
+            },
+            ResourceAllocation {
+                resource_type: "gpus".into(),
+                id: "card1".into(),
+                slots: 2,
+            },
+        ];
+        assert_eq!(
+            parse_resource_group(ctest_output),
+            Ok(("", (3, allocations)))
+        );
+    }
+
+    #[test]
+    fn test_parse_resource_group_ignores_count_and_type() {
+        assert!(parse_resource_group("3:  CTEST_RESOURCE_GROUP_COUNT=1").is_err());
+        assert!(parse_resource_group("3:  CTEST_RESOURCE_GROUP_0=gpus").is_err());
+    }
+
+    #[test]
+    fn test_parse_github_timestamp_prefix() {
+        let ctest_output = "2026-10-17T10:00:00.1234567Z     Start 1: a_test";
+
+        assert_eq!(
+            parse_timestamp_prefix(ctest_output),
+            Ok(("    Start 1: a_test", Duration::new(1792231200, 123456700)))
+        );
+    }
+
+    #[test]
+    fn test_parse_gitlab_timestamp_prefix() {
+        let ctest_output = "2000-02-29T23:59:59.5Z 00O+ Start 1: a_test";
+
+        assert_eq!(
+            parse_timestamp_prefix(ctest_output),
+            Ok(("Start 1: a_test", Duration::new(951868799, 500000000)))
+        );
+    }
+
+    #[test]
+    fn test_parse_timestamp_prefix_without_fraction() {
+        let ctest_output = "2026-10-17T10:00:00Z done";
+
+        assert_eq!(
+            parse_timestamp_prefix(ctest_output),
+            Ok(("done", Duration::new(1792231200, 0)))
+        );
+    }
+
+    #[test]
+    fn test_parse_no_timestamp_prefix() {
+        assert!(parse_timestamp_prefix("    Start 1: a_test").is_err());
+        assert!(parse_timestamp_prefix("2026-10-17 10:00:00 a_test").is_err());
+    }
+
+    #[test]
+    fn test_parse_tests_passed() {
+        assert_eq!(
+            parse_tests_passed("100% tests passed, 0 tests failed out of 24"),
+            Ok(("", (24, 0)))
+        );
+        assert_eq!(
+            parse_tests_passed("96% tests passed, 1 test failed out of 24"),
+            Ok(("", (24, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_total_time() {
+        assert_eq!(
+            parse_total_time("Total Test time (real) =   4.85 sec"),
+            Ok(("", Duration::from_millis(4850)))
         );
     }
 
@@ -186,7 +904,10 @@ mod tests {
         let duration = Duration::from_millis(810);
         assert_eq!(
             parse_test_finish(ctest_output),
-            Ok(("", ("test_stuff".into(), duration)))
+            Ok((
+                "",
+                ("test_stuff".into(), TestStatus::Failed("".into()), duration)
+            ))
         );
     }
 
@@ -197,10 +918,53 @@ mod tests {
         let duration = Duration::from_millis(320) + Duration::new(3, 0);
         assert_eq!(
             parse_test_finish(ctest_output),
-            Ok(("", ("test_me".into(), duration)))
+            Ok(("", ("test_me".into(), TestStatus::Passed, duration)))
+        );
+    }
+
+    #[test]
+    fn test_parse_one_fraction_digit_finish() {
+        let ctest_output = "1/1 Test #1: test_stuff .......................   Passed    10.5 sec";
+
+        let duration = Duration::from_millis(10500);
+        assert_eq!(
+            parse_test_finish(ctest_output),
+            Ok(("", ("test_stuff".into(), TestStatus::Passed, duration)))
+        );
+    }
+
+    #[test]
+    fn test_parse_three_fraction_digits_finish() {
+        let ctest_output = "1/1 Test #1: test_stuff .......................   Passed    0.123 sec";
+
+        let duration = Duration::from_millis(123);
+        assert_eq!(
+            parse_test_finish(ctest_output),
+            Ok(("", ("test_stuff".into(), TestStatus::Passed, duration)))
         );
     }
 
+    #[test]
+    fn test_parse_six_fraction_digits_finish() {
+        let ctest_output =
+            "1/1 Test #1: test_stuff .......................   Passed    2.000456 sec";
+
+        let duration = Duration::new(2, 456_000);
+        assert_eq!(
+            parse_test_finish(ctest_output),
+            Ok(("", ("test_stuff".into(), TestStatus::Passed, duration)))
+        );
+    }
+
+    #[test]
+    fn test_parse_nanos() {
+        assert_eq!(parse_nanos("5"), 500_000_000);
+        assert_eq!(parse_nanos("05"), 50_000_000);
+        assert_eq!(parse_nanos("123"), 123_000_000);
+        assert_eq!(parse_nanos("000001"), 1_000);
+        assert_eq!(parse_nanos("1234567891"), 123_456_789);
+    }
+
     #[test]
     fn test_parse_skipped_test_finish() {
         let ctest_output = "1/1 Test #1: test_stuff ......................***Not Run   0.00 sec";
@@ -208,7 +972,7 @@ mod tests {
         let duration = Duration::new(0, 0);
         assert_eq!(
             parse_test_finish(ctest_output),
-            Ok(("", ("test_stuff".into(), duration)))
+            Ok(("", ("test_stuff".into(), TestStatus::NotRun, duration)))
         );
     }
 
@@ -226,9 +990,11 @@ mod tests {
             parse(reader).unwrap(),
             vec![Trace {
                 name,
+                number: Some(1),
                 start,
                 duration,
-                thread_number: 0
+                thread_number: 0,
+                ..Default::default()
             }]
         );
     }
@@ -247,9 +1013,12 @@ mod tests {
             parse(reader).unwrap(),
             vec![Trace {
                 name,
+                number: Some(1),
                 start,
                 duration,
-                thread_number: 0
+                thread_number: 0,
+                status: TestStatus::Failed("".into()),
+                ..Default::default()
             }]
         );
     }
@@ -277,15 +1046,19 @@ mod tests {
         let second_start = duration;
         let test_1 = Trace {
             name: "test_one".into(),
+            number: Some(1),
             start,
             duration,
             thread_number: 0,
+            ..Default::default()
         };
         let test_2 = Trace {
             name: "test_two".into(),
+            number: Some(2),
             start: second_start,
             duration,
             thread_number: 0,
+            ..Default::default()
         };
         assert_eq!(parse(reader).unwrap(), vec![test_1, test_2]);
     }
@@ -302,15 +1075,19 @@ mod tests {
         let start = Duration::new(0, 0);
         let test_1 = Trace {
             name: "test_one".into(),
+            number: Some(1),
             start,
             duration: Duration::from_millis(200),
             thread_number: 0,
+            ..Default::default()
         };
         let test_2 = Trace {
             name: "test_two".into(),
+            number: Some(2),
             start,
             duration: Duration::from_millis(300),
             thread_number: 1,
+            ..Default::default()
         };
         assert_eq!(parse(reader).unwrap(), vec![test_1, test_2]);
     }
@@ -330,21 +1107,27 @@ mod tests {
         let start = Duration::new(0, 0);
         let test_1 = Trace {
             name: "test_one".into(),
+            number: Some(1),
             start,
             duration: Duration::from_millis(200),
             thread_number: 0,
+            ..Default::default()
         };
         let test_2 = Trace {
             name: "test_two".into(),
+            number: Some(2),
             start,
             duration: Duration::from_millis(300),
             thread_number: 1,
+            ..Default::default()
         };
         let test_3 = Trace {
             name: "test_three".into(),
+            number: Some(3),
             start: Duration::from_millis(200),
             duration: Duration::from_millis(500),
             thread_number: 0,
+            ..Default::default()
         };
         assert_eq!(parse(reader).unwrap(), vec![test_1, test_2, test_3]);
     }
@@ -370,27 +1153,35 @@ mod tests {
         let start = Duration::new(0, 0);
         let test_1 = Trace {
             name: "test_one".into(),
+            number: Some(1),
             start,
             duration: Duration::from_millis(200),
             thread_number: 0,
+            ..Default::default()
         };
         let test_2 = Trace {
             name: "test_two".into(),
+            number: Some(2),
             start,
             duration: Duration::new(10, 0),
             thread_number: 1,
+            ..Default::default()
         };
         let test_3 = Trace {
             name: "test_three".into(),
+            number: Some(3),
             start: Duration::from_millis(200),
             duration: Duration::from_millis(500),
             thread_number: 0,
+            ..Default::default()
         };
         let test_4 = Trace {
             name: "test_four".into(),
+            number: Some(4),
             start: Duration::from_millis(200),
             duration: Duration::from_millis(500),
             thread_number: 2,
+            ..Default::default()
         };
         assert_eq!(parse(reader).unwrap(), vec![test_1, test_3, test_4, test_2]);
     }
@@ -412,31 +1203,146 @@ mod tests {
         let start = Duration::new(0, 0);
         let test_1 = Trace {
             name: "test_one".into(),
+            number: Some(1),
             start,
             duration: Duration::from_millis(200),
             thread_number: 0,
+            ..Default::default()
         };
         let test_2 = Trace {
             name: "test_two".into(),
+            number: Some(2),
             start,
             duration: Duration::new(10, 0),
             thread_number: 1,
+            ..Default::default()
         };
         let test_3 = Trace {
             name: "test_three".into(),
+            number: Some(3),
             start: Duration::new(10, 0),
             duration: Duration::from_millis(500),
             thread_number: 0,
+            ..Default::default()
         };
         let test_4 = Trace {
             name: "test_four".into(),
+            number: Some(4),
             start: Duration::new(10, 0),
             duration: Duration::from_millis(500),
             thread_number: 1,
+            ..Default::default()
         };
         assert_eq!(parse(reader).unwrap(), vec![test_1, test_2, test_3, test_4]);
     }
 
+    #[test]
+    fn test_parse_multiple_processors() {
+        // `test_two` needs 2 processors so holds threads 1 and 2, when it finishes both are
+        // returned for `test_three` and `test_four` to use.
+        let ctest_output = r#"
+                Start  1: test_one
+                Start  2: test_two
+            1/4 Test #2: test_two ......................   Passed   0.20 sec
+                Start  3: test_three
+                Start  4: test_four
+            2/4 Test #1: test_one ......................   Passed   0.50 sec
+            3/4 Test #3: test_three ......................   Passed   0.10 sec
+            4/4 Test #4: test_four ......................   Passed   0.10 sec
+            "#;
+
+        let mut options = ParseOptions::default();
+        options.test_info.set_processors("test_two", 2);
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let start = Duration::new(0, 0);
+        let test_2 = Trace {
+            name: "test_two".into(),
+            number: Some(2),
+            start,
+            duration: Duration::from_millis(200),
+            thread_number: 1,
+            extra_threads: vec![2],
+            ..Default::default()
+        };
+        let test_1 = Trace {
+            name: "test_one".into(),
+            number: Some(1),
+            start,
+            duration: Duration::from_millis(500),
+            thread_number: 0,
+            ..Default::default()
+        };
+        let test_3 = Trace {
+            name: "test_three".into(),
+            number: Some(3),
+            start: Duration::from_millis(200),
+            duration: Duration::from_millis(100),
+            thread_number: 1,
+            ..Default::default()
+        };
+        let test_4 = Trace {
+            name: "test_four".into(),
+            number: Some(4),
+            start: Duration::from_millis(200),
+            duration: Duration::from_millis(100),
+            thread_number: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_with_options(reader, &options).unwrap().traces,
+            vec![test_2, test_1, test_3, test_4]
+        );
+    }
+
+    #[test]
+    fn test_parse_labels_from_test_info() {
+        let ctest_output = r#"
+                Start  3: query
+            1/1 Test #3: query ......................   Passed   0.20 sec"#;
+
+        let mut options = ParseOptions::default();
+        options.test_info.insert(
+            "query",
+            crate::test_info::TestProperties {
+                labels: vec!["db".into()],
+                ..Default::default()
+            },
+        );
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let run = parse_with_options(reader, &options).unwrap();
+        assert_eq!(run.traces[0].number, Some(3));
+        assert_eq!(run.traces[0].labels, vec!["db"]);
+    }
+
+    #[test]
+    fn test_parse_verbose_resource_groups() {
+        let ctest_output = r#"
+            test 1
+                Start 1: gpu_test
+
+            1: Test command: /bin/gpu_test
+            1: Environment variables:
+            1:  CTEST_RESOURCE_GROUP_COUNT=1
+            1:  CTEST_RESOURCE_GROUP_0=gpus
+            1:  CTEST_RESOURCE_GROUP_0_GPUS=id:1,slots:2
+            1/1 Test #1: gpu_test ......................   Passed   0.20 sec
+            "#;
+
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let trace = Trace {
+            name: "gpu_test".into(),
+            number: Some(1),
+            duration: Duration::from_millis(200),
+            resources: vec![ResourceAllocation {
+                resource_type: "gpus".into(),
+                id: "1".into(),
+                slots: 2,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(parse(reader).unwrap(), vec![trace]);
+    }
+
     #[test]
     fn test_parse_orphaned_finish() {
         let ctest_output = r#"
@@ -444,10 +1350,22 @@ mod tests {
             "#;
 
         let reader = BufReader::new(ctest_output.as_bytes());
-        let message = "Saw end of \"test_one\" without start indicator";
         let error = parse(reader).unwrap_err();
-        let parse_error = error.downcast_ref::<ParseError>().unwrap();
-        assert_eq!(*parse_error, ParseError::new(message));
+        match &error {
+            ParseError::OrphanFinish { line, text, name } => {
+                assert_eq!(*line, 2);
+                assert_eq!(
+                    text,
+                    "            1/1 Test #1: test_one ......................   Passed   0.50 sec"
+                );
+                assert_eq!(name, "test_one");
+            }
+            _ => panic!("unexpected error {:?}", error),
+        }
+        assert_eq!(
+            error.to_string(),
+            "Saw end of \"test_one\" without start indicator"
+        );
     }
 
     #[test]
@@ -457,9 +1375,369 @@ mod tests {
             "#;
 
         let reader = BufReader::new(ctest_output.as_bytes());
-        let message = "Saw end of \"test_two\" without start indicator";
         let error = parse(reader).unwrap_err();
-        let parse_error = error.downcast_ref::<ParseError>().unwrap();
-        assert_eq!(*parse_error, ParseError::new(message));
+        assert!(matches!(&error, ParseError::OrphanFinish { name, .. } if name == "test_two"));
+        assert_eq!(
+            error.to_string(),
+            "Saw end of \"test_two\" without start indicator"
+        );
+    }
+
+    #[test]
+    fn test_parse_duplicate_start() {
+        let ctest_output = r#"
+                Start  1: test_one
+                Start  1: test_one
+            1/1 Test #1: test_one ......................   Passed   0.50 sec
+            "#;
+
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let error = parse(reader).unwrap_err();
+        assert!(matches!(error, ParseError::DuplicateStart { line: 3, .. }));
+        assert_eq!(
+            error.to_string(),
+            "Saw start of \"test_one\" while it was already running"
+        );
+    }
+
+    #[test]
+    fn test_parse_duplicate_start_leniently() {
+        let ctest_output = r#"
+                Start  1: test_one
+                Start  1: test_one
+            1/1 Test #1: test_one ......................   Passed   0.50 sec
+            "#;
+
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let run = parse_with_options(reader, &options).unwrap();
+        assert_eq!(run.traces.len(), 1);
+        assert_eq!(run.traces[0].thread_number, 0);
+        assert_eq!(run.diagnostics.len(), 1);
+        assert_eq!(run.diagnostics[0].line, 3);
+    }
+
+    #[test]
+    fn test_parse_invalid_encoding() {
+        let ctest_output =
+            b"    Start  1: test_\xff\n1/1 Test #1: test_\xff ....   Passed   0.50 sec\n";
+
+        let reader = BufReader::new(&ctest_output[..]);
+        let error = parse(reader).unwrap_err();
+        match error {
+            ParseError::InvalidEncoding { line, text } => {
+                assert_eq!(line, 1);
+                assert_eq!(text, "    Start  1: test_\u{fffd}");
+            }
+            _ => panic!("unexpected error {:?}", error),
+        }
+
+        let reader = BufReader::new(&ctest_output[..]);
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let run = parse_with_options(reader, &options).unwrap();
+        assert_eq!(run.traces[0].name, "test_\u{fffd}");
+    }
+
+    #[test]
+    fn test_parse_read_failure() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk on fire"))
+            }
+        }
+
+        let error = parse(BufReader::new(Failing)).unwrap_err();
+        assert!(matches!(error, ParseError::Io { line: 1, .. }));
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn test_error_snippet() {
+        let error = ParseError::OrphanFinish {
+            line: 12,
+            text: "1/1 Test #1: a ...   Passed   0.50 sec".into(),
+            name: "a".into(),
+        };
+        let expected = r#"error: Saw end of "a" without start indicator
+  --> line 12
+   |
+12 | 1/1 Test #1: a ...   Passed   0.50 sec
+   |"#;
+        assert_eq!(error.snippet(), expected);
+    }
+
+    #[test]
+    fn test_parse_timestamped_lines() {
+        // The timestamps show an idle gap before `test_two` starts, which can't be inferred
+        // from the ctest output alone.
+        let ctest_output = r#"2026-10-17T10:00:00.0000000Z Test project /tmp/build
+2026-10-17T10:00:01.0000000Z     Start 1: test_one
+2026-10-17T10:00:01.2000000Z 1/2 Test #1: test_one ......................   Passed   0.20 sec
+2026-10-17T10:00:03.5000000Z     Start 2: test_two
+2026-10-17T10:00:03.8000000Z 2/2 Test #2: test_two ......................   Passed   0.30 sec
+"#;
+
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let test_1 = Trace {
+            name: "test_one".into(),
+            number: Some(1),
+            duration: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let test_2 = Trace {
+            name: "test_two".into(),
+            number: Some(2),
+            start: Duration::from_millis(2500),
+            duration: Duration::from_millis(300),
+            ..Default::default()
+        };
+        let run = parse_with_options(reader, &ParseOptions::default()).unwrap();
+        assert_eq!(run.traces, vec![test_1, test_2]);
+        assert_eq!(
+            run.start_time,
+            Some(UNIX_EPOCH + Duration::new(1792231201, 0))
+        );
+    }
+
+    #[test]
+    fn test_parse_timestamped_duration() {
+        // ctest rounds both durations to 0.00 sec, the timestamps show how long they took
+        let ctest_output = r#"2026-10-17T10:00:00.0000000Z     Start 1: test_one
+2026-10-17T10:00:00.0040000Z 1/2 Test #1: test_one ......................   Passed   0.00 sec
+2026-10-17T10:00:00.0040000Z     Start 2: test_two
+2026-10-17T10:00:00.0090000Z 2/2 Test #2: test_two ......................   Passed   0.00 sec
+"#;
+
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let test_1 = Trace {
+            name: "test_one".into(),
+            number: Some(1),
+            duration: Duration::from_millis(4),
+            ..Default::default()
+        };
+        let test_2 = Trace {
+            name: "test_two".into(),
+            number: Some(2),
+            start: Duration::from_millis(4),
+            duration: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let run = parse_with_options(reader, &ParseOptions::default()).unwrap();
+        assert_eq!(run.traces, vec![test_1, test_2]);
+    }
+
+    #[test]
+    fn test_parse_msbuild_colored_crlf_output() {
+        let ctest_output = "1>  Test project C:/build\r\n\
+            1>      Start 1: test_one\r\n\
+            1>  1/1 Test #1: test_one ......   \x1b[32mPassed\x1b[0m    0.20 sec\r\n";
+
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let trace = Trace {
+            name: "test_one".into(),
+            number: Some(1),
+            duration: Duration::from_millis(200),
+            ..Default::default()
+        };
+        assert_eq!(parse(reader).unwrap(), vec![trace]);
+    }
+
+    #[test]
+    fn test_parse_invalid_utf8_output() {
+        let mut ctest_output = b"    Start  1: test_one\n1: binary \xff\xfe\x00 junk\n".to_vec();
+        ctest_output.extend_from_slice(b"\xc3\x28\n");
+        ctest_output.extend_from_slice(b"1/1 Test #1: test_one ........   Passed   0.20 sec\n");
+
+        let reader = BufReader::new(ctest_output.as_slice());
+        let trace = Trace {
+            name: "test_one".into(),
+            number: Some(1),
+            duration: Duration::from_millis(200),
+            ..Default::default()
+        };
+        assert_eq!(parse(reader).unwrap(), vec![trace]);
+    }
+
+    #[test]
+    fn test_parse_lenient_orphaned_finish() {
+        let ctest_output = r#"
+            1/2 Test #1: test_one ......................   Passed   0.50 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................   Passed   0.20 sec
+            "#;
+
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let trace = Trace {
+            name: "test_two".into(),
+            number: Some(2),
+            duration: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let diagnostic = Diagnostic {
+            line: 2,
+            text: "            1/2 Test #1: test_one ......................   Passed   0.50 sec"
+                .into(),
+            message: "Saw end of \"test_one\" without start indicator".into(),
+        };
+        assert_eq!(
+            parse_with_options(reader, &options).unwrap(),
+            Run {
+                traces: vec![trace],
+                diagnostics: vec![diagnostic],
+                start_time: None,
+                summary: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_push_line_events() {
+        let mut parser = Parser::new(ParseOptions::default());
+
+        assert_eq!(parser.push_line("Test project /tmp/build").unwrap(), None);
+        assert_eq!(
+            parser.push_line("    Start 1: test_one").unwrap(),
+            Some(ParseEvent::TestStarted {
+                name: "test_one".into(),
+                number: 1,
+                start: Duration::new(0, 0),
+                thread_number: 0,
+                extra_threads: vec![],
+            })
+        );
+        let trace = Trace {
+            name: "test_one".into(),
+            number: Some(1),
+            duration: Duration::from_millis(200),
+            ..Default::default()
+        };
+        assert_eq!(
+            parser
+                .push_line("1/1 Test #1: test_one .........   Passed    0.20 sec")
+                .unwrap(),
+            Some(ParseEvent::TestFinished(trace))
+        );
+        assert_eq!(
+            parser
+                .push_line("100% tests passed, 0 tests failed out of 1")
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            parser
+                .push_line("Total Test time (real) =   0.21 sec")
+                .unwrap(),
+            Some(ParseEvent::Summary(Summary {
+                total: 1,
+                failed: 0,
+                total_time: Duration::from_millis(210),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_push_line_orphaned_finish() {
+        let mut parser = Parser::new(ParseOptions::default());
+
+        assert!(parser
+            .push_line("1/1 Test #1: test_one .........   Passed    0.20 sec")
+            .is_err());
+    }
+
+    #[test]
+    fn test_events_iterator() {
+        let ctest_output = r#"
+                Start  1: test_one
+                Start  2: test_two
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+            2/2 Test #2: test_two ......................***Failed   0.30 sec
+
+            50% tests passed, 1 test failed out of 2
+
+            Total Test time (real) =   0.31 sec
+            "#;
+
+        let events: Vec<ParseEvent> = Events::new(ctest_output.as_bytes(), ParseOptions::default())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|e| match e {
+                ParseEvent::TestStarted { .. } => "started",
+                ParseEvent::TestFinished(_) => "finished",
+                ParseEvent::Summary(_) => "summary",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec!["started", "started", "finished", "finished", "summary"]
+        );
+    }
+
+    #[test]
+    fn test_events_iterator_stops_after_error() {
+        let ctest_output = r#"
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+            2/2 Test #2: test_two ......................   Passed   0.30 sec
+            "#;
+
+        let mut events = Events::new(ctest_output.as_bytes(), ParseOptions::default());
+        assert!(events.next().unwrap().is_err());
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        let ctest_output = r#"
+                Start  1: test_one
+            1/1 Test #1: test_one ......................   Passed   0.20 sec
+
+            100% tests passed, 0 tests failed out of 1
+
+            Total Test time (real) =   0.25 sec
+            "#;
+
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let run = parse_with_options(reader, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            run.summary,
+            Some(Summary {
+                total: 1,
+                failed: 0,
+                total_time: Duration::from_millis(250),
+            })
+        );
+    }
+
+    #[test]
+    fn test_wall_time() {
+        let ctest_output = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................   Passed   0.30 sec
+            "#;
+
+        let reader = BufReader::new(ctest_output.as_bytes());
+        let mut run = parse_with_options(reader, &ParseOptions::default()).unwrap();
+        assert_eq!(run.wall_time(), Duration::from_millis(500));
+
+        run.summary = Some(Summary {
+            total_time: Duration::from_secs(1),
+            ..Default::default()
+        });
+        assert_eq!(run.wall_time(), Duration::from_secs(1));
+        assert_eq!(Run::default().wall_time(), Duration::new(0, 0));
     }
 }
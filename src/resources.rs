@@ -0,0 +1,341 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::chrome::Metadata;
+use crate::trace::Trace;
+
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::time::Duration;
+
+/// The process identifier used for the resource tracks, keeping them apart
+/// from the test threads which use process `0`.
+pub const RESOURCE_PROCESS: u32 = 1;
+
+/// Slots of a resource given to a test by ctest, as seen in the
+/// `CTEST_RESOURCE_GROUP_<num>_<type>` environment variables.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ResourceAllocation {
+    /// The resource type, lower case as in the resource spec file
+    pub resource_type: String,
+    pub id: String,
+    pub slots: u32,
+}
+
+//  The resource spec file format, see
+//  https://cmake.org/cmake/help/latest/manual/ctest.1.html#resource-specification-file
+#[derive(Deserialize)]
+struct SpecFile {
+    local: Vec<BTreeMap<String, Vec<SpecResource>>>,
+}
+
+#[derive(Deserialize)]
+struct SpecResource {
+    id: String,
+    #[serde(default = "default_slots")]
+    slots: u32,
+}
+
+fn default_slots() -> u32 {
+    1
+}
+
+/// A single slot of a resource, which gets its own track in the trace.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ResourceSlot {
+    pub resource_type: String,
+    pub id: String,
+    pub slot: u32,
+}
+
+impl ResourceSlot {
+    /// The name of the track for this slot, i.e. "gpus 0 slot 1".
+    pub fn track_name(&self) -> String {
+        format!("{} {} slot {}", self.resource_type, self.id, self.slot)
+    }
+}
+
+/// The resources available to ctest, from a `--resource-spec-file`.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ResourceSpec {
+    slots: Vec<ResourceSlot>,
+}
+
+impl ResourceSpec {
+    /// Reads a ctest resource spec file.  Only the first, and only
+    /// supported, entry of `local` is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`serde_json::Error`](serde_json::Error) when the `reader` does
+    /// not contain a valid resource spec.
+    pub fn from_json<R: Read>(reader: R) -> Result<ResourceSpec, serde_json::Error> {
+        let file: SpecFile = serde_json::from_reader(reader)?;
+        let mut slots = vec![];
+        if let Some(local) = file.local.into_iter().next() {
+            for (resource_type, resources) in local {
+                for resource in resources {
+                    for slot in 0..resource.slots {
+                        slots.push(ResourceSlot {
+                            resource_type: resource_type.clone(),
+                            id: resource.id.clone(),
+                            slot,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(ResourceSpec { slots })
+    }
+
+    /// Every slot of every resource, in track order.
+    pub fn slots(&self) -> &[ResourceSlot] {
+        &self.slots
+    }
+
+    /// The metadata naming the resource process and each slot's track.
+    pub fn metadata(&self) -> Vec<Metadata> {
+        let process = Metadata::ProcessName {
+            pid: RESOURCE_PROCESS,
+            name: "resources".into(),
+        };
+        let tracks = self
+            .slots
+            .iter()
+            .enumerate()
+            .map(|(track, slot)| Metadata::ThreadName {
+                pid: RESOURCE_PROCESS,
+                tid: track as u32,
+                name: slot.track_name(),
+            });
+        std::iter::once(process).chain(tracks).collect()
+    }
+}
+
+/// A test occupying a resource slot's track.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ResourceEvent {
+    pub name: String,
+    pub start: Duration,
+    pub duration: Duration,
+    pub track: u32,
+}
+
+impl Serialize for ResourceEvent {
+    //  The same complete event as a `Trace`, but on the resource process
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(7))?;
+        map.serialize_entry("name", &self.name)?;
+        map.serialize_entry("cat", "resource")?;
+        map.serialize_entry("ph", "X")?;
+        let start = self.start.as_micros() as u64;
+        map.serialize_entry("ts", &start)?;
+        let duration = self.duration.as_micros() as u64;
+        map.serialize_entry("dur", &duration)?;
+        map.serialize_entry("pid", &RESOURCE_PROCESS)?;
+        map.serialize_entry("tid", &self.track)?;
+        map.end()
+    }
+}
+
+/// Lays out the tests in `traces` on the resource slots they held.
+///
+/// ctest only reports which resource, and how many of its slots, a test was
+/// given.  Each test is placed on the lowest numbered slots of that resource
+/// which are free when it starts.
+pub fn resource_events(spec: &ResourceSpec, traces: &[Trace]) -> Vec<ResourceEvent> {
+    let mut busy_until = vec![Duration::new(0, 0); spec.slots.len()];
+    let mut ordered: Vec<&Trace> = traces.iter().collect();
+    ordered.sort_by_key(|t| t.start);
+
+    let mut events = vec![];
+    for trace in ordered {
+        for allocation in &trace.resources {
+            let mut candidates: Vec<usize> = spec
+                .slots
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| {
+                    s.resource_type == allocation.resource_type && s.id == allocation.id
+                })
+                .map(|(track, _)| track)
+                .collect();
+            // Free slots first, then those freeing up soonest, should ctest's
+            // inferred timing not line up.
+            candidates.sort_by_key(|track| (busy_until[*track] > trace.start, busy_until[*track]));
+            for track in candidates.into_iter().take(allocation.slots as usize) {
+                busy_until[track] = trace.start + trace.duration;
+                events.push(ResourceEvent {
+                    name: trace.name.clone(),
+                    start: trace.start,
+                    duration: trace.duration,
+                    track: track as u32,
+                });
+            }
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_test::{assert_ser_tokens, Token};
+
+    const SPEC: &str = r#"{
+        "version": {"major": 1, "minor": 0},
+        "local": [
+            {
+                "gpus": [{"id": "0", "slots": 2}, {"id": "1", "slots": 1}],
+                "crypto_chips": [{"id": "card0"}]
+            }
+        ]
+    }"#;
+
+    fn slot(resource_type: &str, id: &str, slot: u32) -> ResourceSlot {
+        ResourceSlot {
+            resource_type: resource_type.into(),
+            id: id.into(),
+            slot,
+        }
+    }
+
+    fn gpu_test(name: &str, start: u64, duration: u64, id: &str, slots: u32) -> Trace {
+        Trace {
+            name: name.into(),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(duration),
+            resources: vec![ResourceAllocation {
+                resource_type: "gpus".into(),
+                id: id.into(),
+                slots,
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn event(name: &str, start: u64, duration: u64, track: u32) -> ResourceEvent {
+        ResourceEvent {
+            name: name.into(),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(duration),
+            track,
+        }
+    }
+
+    #[test]
+    fn test_read_spec() {
+        let spec = ResourceSpec::from_json(SPEC.as_bytes()).unwrap();
+        assert_eq!(
+            spec.slots(),
+            &[
+                slot("crypto_chips", "card0", 0),
+                slot("gpus", "0", 0),
+                slot("gpus", "0", 1),
+                slot("gpus", "1", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_spec() {
+        assert!(ResourceSpec::from_json("{}".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_metadata_names_tracks() {
+        let spec = ResourceSpec::from_json(SPEC.as_bytes()).unwrap();
+        let metadata = spec.metadata();
+        assert_eq!(metadata.len(), 5);
+        assert_eq!(
+            metadata[2],
+            Metadata::ThreadName {
+                pid: 1,
+                tid: 1,
+                name: "gpus 0 slot 0".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_track_name() {
+        assert_eq!(slot("gpus", "0", 1).track_name(), "gpus 0 slot 1");
+    }
+
+    #[test]
+    fn test_serialize_resource_event() {
+        assert_ser_tokens(
+            &event("foo", 1, 2, 3),
+            &[
+                Token::Map { len: Some(7) },
+                Token::String("name"),
+                Token::String("foo"),
+                Token::String("cat"),
+                Token::String("resource"),
+                Token::String("ph"),
+                Token::String("X"),
+                Token::String("ts"),
+                Token::U64(1000),
+                Token::String("dur"),
+                Token::U64(2000),
+                Token::String("pid"),
+                Token::U32(1),
+                Token::String("tid"),
+                Token::U32(3),
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_overlapping_tests_use_different_slots() {
+        let spec = ResourceSpec::from_json(SPEC.as_bytes()).unwrap();
+        let traces = vec![
+            gpu_test("one", 0, 100, "0", 1),
+            gpu_test("two", 50, 100, "0", 1),
+            gpu_test("three", 100, 100, "0", 1),
+        ];
+
+        assert_eq!(
+            resource_events(&spec, &traces),
+            vec![
+                event("one", 0, 100, 1),
+                event("two", 50, 100, 2),
+                event("three", 100, 100, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiple_slots() {
+        let spec = ResourceSpec::from_json(SPEC.as_bytes()).unwrap();
+        let traces = vec![
+            gpu_test("big", 0, 100, "0", 2),
+            gpu_test("other", 0, 100, "1", 1),
+        ];
+
+        assert_eq!(
+            resource_events(&spec, &traces),
+            vec![
+                event("big", 0, 100, 1),
+                event("big", 0, 100, 2),
+                event("other", 0, 100, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_resource_is_ignored() {
+        let spec = ResourceSpec::from_json(SPEC.as_bytes()).unwrap();
+        let traces = vec![gpu_test("one", 0, 100, "7", 1)];
+
+        assert_eq!(resource_events(&spec, &traces), vec![]);
+    }
+}
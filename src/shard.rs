@@ -0,0 +1,273 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::trace::Trace;
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// A group of tests to run together, i.e. on one CI machine.
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Shard {
+    /// The names of the tests, longest first
+    pub tests: Vec<String>,
+    /// How long running the tests should take, with the parallelism the
+    /// shard was planned for
+    pub estimated_time: Duration,
+}
+
+impl Serialize for Shard {
+    //  A shard as JSON:
+    //
+    //      {
+    //         "tests": ["string"],
+    //         "estimated_seconds": float
+    //     }
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("tests", &self.tests)?;
+        map.serialize_entry("estimated_seconds", &self.estimated_time.as_secs_f64())?;
+        map.end()
+    }
+}
+
+impl Shard {
+    /// The arguments for ctest to run only the tests of this shard, i.e.
+    /// `-R '^(test_one|test_two)$'`, quoted for a POSIX shell.
+    pub fn ctest_arguments(&self) -> String {
+        // ctest's regular expressions treat any character after a backslash
+        // literally, so escaping everything `regex` would is safe
+        let names: Vec<String> = self.tests.iter().map(|t| regex::escape(t)).collect();
+        let pattern = format!("^({})$", names.join("|"));
+        format!("-R '{}'", pattern.replace('\'', r"'\''"))
+    }
+
+    /// A CMake `set()` of the list of tests in this shard to the variable
+    /// `name`.
+    pub fn cmake_list(&self, name: &str) -> String {
+        let escaped: Vec<String> = self
+            .tests
+            .iter()
+            .map(|t| {
+                t.replace('\\', r"\\")
+                    .replace('"', "\\\"")
+                    .replace('$', "\\$")
+                    .replace(';', "\\;")
+            })
+            .collect();
+        format!("set({} \"{}\")", name, escaped.join(";"))
+    }
+}
+
+// The work of a shard being planned, each lane is when that job is free
+struct Plan {
+    lanes: Vec<Duration>,
+    tests: Vec<String>,
+}
+
+impl Plan {
+    // The lanes a test using `processors` would run on, and when it could
+    // start on all of them
+    fn place(&self, processors: usize) -> (Vec<usize>, Duration) {
+        let mut order: Vec<usize> = (0..self.lanes.len()).collect();
+        order.sort_by_key(|lane| (self.lanes[*lane], *lane));
+        order.truncate(processors.clamp(1, self.lanes.len()));
+        let start = order
+            .iter()
+            .map(|l| self.lanes[*l])
+            .max()
+            .unwrap_or_default();
+        (order, start)
+    }
+
+    fn finish(&self) -> Duration {
+        self.lanes.iter().copied().max().unwrap_or_default()
+    }
+}
+
+/// Partitions the tests in `traces` into `shards` groups which should take
+/// about the same time when each is run with `parallel` jobs, i.e.
+/// `ctest -j<parallel>`.
+///
+/// This is the longest processing time heuristic, where the longest tests
+/// are placed first, each in the shard where it would finish soonest.  Each
+/// shard is modeled as `parallel` jobs, so a shard holding one very long
+/// test gets fewer short tests, and a test using several processors holds
+/// that many jobs.
+///
+/// A test in `traces` more than once, i.e. from several runs, uses its
+/// average duration.  With more `shards` than tests, the extra shards are
+/// empty.
+pub fn plan(traces: &[Trace], shards: usize, parallel: usize) -> Vec<Shard> {
+    // Sorted by name so the plan doesn't depend on the order of `traces`
+    let mut tests: BTreeMap<&str, (Duration, u32, usize)> = BTreeMap::new();
+    for trace in traces {
+        let entry = tests
+            .entry(&trace.name)
+            .or_insert((Duration::new(0, 0), 0, 1));
+        entry.0 += trace.duration;
+        entry.1 += 1;
+        entry.2 = entry.2.max(trace.processors());
+    }
+    let mut tests: Vec<(&str, Duration, usize)> = tests
+        .into_iter()
+        .map(|(name, (total, runs, processors))| (name, total / runs, processors))
+        .collect();
+    tests.sort_by(|a, b| {
+        let work = |t: &(&str, Duration, usize)| t.1 * t.2 as u32;
+        work(b).cmp(&work(a)).then(a.0.cmp(b.0))
+    });
+
+    let mut plans: Vec<Plan> = (0..shards)
+        .map(|_| Plan {
+            lanes: vec![Duration::new(0, 0); parallel.max(1)],
+            tests: vec![],
+        })
+        .collect();
+    for (name, duration, processors) in tests {
+        let best = plans
+            .iter()
+            .enumerate()
+            .min_by_key(|(index, plan)| {
+                let (_, start) = plan.place(processors);
+                let finish = plan.finish().max(start + duration);
+                let work: Duration = plan.lanes.iter().sum();
+                (finish, work, *index)
+            })
+            .map(|(index, _)| index);
+        if let Some(best) = best {
+            let plan = &mut plans[best];
+            let (lanes, start) = plan.place(processors);
+            for lane in lanes {
+                plan.lanes[lane] = start + duration;
+            }
+            plan.tests.push(name.to_string());
+        }
+    }
+
+    plans
+        .into_iter()
+        .map(|plan| Shard {
+            estimated_time: plan.finish(),
+            tests: plan.tests,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(name: &str, seconds: u64) -> Trace {
+        Trace::new(name, Duration::new(0, 0), Duration::from_secs(seconds))
+    }
+
+    fn names(shard: &Shard) -> Vec<&str> {
+        shard.tests.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn test_longest_first_into_least_loaded() {
+        let traces = vec![
+            test("a", 5),
+            test("b", 4),
+            test("c", 3),
+            test("d", 3),
+            test("e", 3),
+        ];
+
+        let shards = plan(&traces, 2, 1);
+        assert_eq!(names(&shards[0]), vec!["a", "d"]);
+        assert_eq!(names(&shards[1]), vec!["b", "c", "e"]);
+        assert_eq!(shards[0].estimated_time, Duration::from_secs(8));
+        assert_eq!(shards[1].estimated_time, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_parallel_jobs() {
+        // With two jobs the long test gets a shard to itself, the short ones
+        // run side by side in the other
+        let traces = vec![
+            test("long", 10),
+            test("a", 5),
+            test("b", 5),
+            test("c", 5),
+            test("d", 5),
+        ];
+
+        let shards = plan(&traces, 2, 2);
+        assert_eq!(names(&shards[0]), vec!["long", "c"]);
+        assert_eq!(names(&shards[1]), vec!["a", "b", "d"]);
+        assert_eq!(shards[0].estimated_time, Duration::from_secs(10));
+        assert_eq!(shards[1].estimated_time, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_multiple_processors_hold_jobs() {
+        let mut big = test("big", 4);
+        big.extra_threads = vec![1];
+        let traces = vec![big, test("small", 3)];
+
+        let shards = plan(&traces, 1, 2);
+        assert_eq!(names(&shards[0]), vec!["big", "small"]);
+        assert_eq!(shards[0].estimated_time, Duration::from_secs(7));
+    }
+
+    #[test]
+    fn test_repeated_tests_use_average() {
+        let traces = vec![test("a", 2), test("a", 4), test("b", 1)];
+
+        let shards = plan(&traces, 1, 1);
+        assert_eq!(names(&shards[0]), vec!["a", "b"]);
+        assert_eq!(shards[0].estimated_time, Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_more_shards_than_tests() {
+        let shards = plan(&[test("a", 1)], 3, 1);
+        assert_eq!(shards.len(), 3);
+        assert_eq!(names(&shards[0]), vec!["a"]);
+        assert!(shards[1].tests.is_empty());
+        assert_eq!(shards[2].estimated_time, Duration::new(0, 0));
+    }
+
+    #[test]
+    fn test_ctest_arguments() {
+        let shard = Shard {
+            tests: vec!["a.b".into(), "it's".into(), "c++".into()],
+            ..Default::default()
+        };
+        assert_eq!(shard.ctest_arguments(), r"-R '^(a\.b|it'\''s|c\+\+)$'");
+    }
+
+    #[test]
+    fn test_cmake_list() {
+        let shard = Shard {
+            tests: vec!["a".into(), "say \"hi\"".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            shard.cmake_list("CTEST_SHARD_0"),
+            r#"set(CTEST_SHARD_0 "a;say \"hi\"")"#
+        );
+    }
+
+    #[test]
+    fn test_serialize_shard() {
+        let shard = Shard {
+            tests: vec!["a".into()],
+            estimated_time: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            serde_json::to_string(&shard).unwrap(),
+            r#"{"tests":["a"],"estimated_seconds":1.5}"#
+        );
+    }
+}
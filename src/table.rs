@@ -0,0 +1,246 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::trace::Trace;
+
+use serde_json::Value;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+/// A column of the table of tests, written by [`write_csv()`](write_csv) and
+/// [`write_jsonl()`](write_jsonl).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// The name of the test
+    Name,
+    /// The number ctest gave the test, empty when not known
+    Number,
+    /// When the test started, in seconds from the start of the run
+    Start,
+    /// How long the test took, in seconds
+    Duration,
+    /// The lane, or thread, the test ran in
+    Lane,
+    /// How the test finished, as ctest reported it
+    Status,
+    /// The test's `LABELS` property, `;` separated in CSV
+    Labels,
+    /// The identifier given for the run, empty when none was given
+    RunId,
+}
+
+impl Column {
+    /// All the columns, in the order they're written.
+    pub const ALL: [Column; 8] = [
+        Column::Name,
+        Column::Number,
+        Column::Start,
+        Column::Duration,
+        Column::Lane,
+        Column::Status,
+        Column::Labels,
+        Column::RunId,
+    ];
+
+    /// The name of the column, in the header and as the key of a JSON object.
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Name => "name",
+            Column::Number => "number",
+            Column::Start => "start_seconds",
+            Column::Duration => "duration_seconds",
+            Column::Lane => "lane",
+            Column::Status => "status",
+            Column::Labels => "labels",
+            Column::RunId => "run_id",
+        }
+    }
+
+    fn value(self, trace: &Trace, run_id: Option<&str>) -> Value {
+        match self {
+            Column::Name => trace.name.clone().into(),
+            Column::Number => trace.number.into(),
+            Column::Start => trace.start.as_secs_f64().into(),
+            Column::Duration => trace.duration.as_secs_f64().into(),
+            Column::Lane => trace.thread_number.into(),
+            Column::Status => trace.status.to_string().into(),
+            Column::Labels => trace.labels.clone().into(),
+            Column::RunId => run_id.into(),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Column::ALL
+            .iter()
+            .copied()
+            .find(|c| c.name() == value)
+            .ok_or_else(|| format!("Unknown column \"{}\"", value))
+    }
+}
+
+// The columns to write, all of them when none are selected, always in the
+// order of `Column::ALL` so the header is the same however they're selected
+fn selected(columns: &[Column]) -> Vec<Column> {
+    Column::ALL
+        .iter()
+        .copied()
+        .filter(|c| columns.is_empty() || columns.contains(c))
+        .collect()
+}
+
+// Quotes a CSV field which has a delimiter, quote, or line ending in it
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(values) => values
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(";"),
+        value => value.to_string(),
+    };
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// Writes `traces` as CSV, with a header and a row for each test.
+///
+/// Only the `columns` given are written, or all of them when none are, and
+/// always in the order of [`Column::ALL`](Column::ALL).  Fields are quoted as
+/// in RFC 4180.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_csv<W: Write>(
+    mut writer: W,
+    traces: &[Trace],
+    columns: &[Column],
+    run_id: Option<&str>,
+) -> io::Result<()> {
+    //  A header, then a row for each test:
+    //
+    //      name,number,start_seconds,duration_seconds,lane,status,labels,run_id
+    //      test_one,1,0.0,0.2,0,Passed,db;slow,nightly
+    let columns = selected(columns);
+    let header: Vec<&str> = columns.iter().map(|c| c.name()).collect();
+    writeln!(writer, "{}", header.join(","))?;
+    for trace in traces {
+        let row: Vec<String> = columns
+            .iter()
+            .map(|c| csv_field(&c.value(trace, run_id)))
+            .collect();
+        writeln!(writer, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+/// Writes `traces` as JSON Lines, a JSON object on a line for each test.
+///
+/// The keys are the names of the `columns` given, or all of them when none
+/// are, in the order of [`Column::ALL`](Column::ALL).  Unknown numbers and run
+/// identifiers are `null`, and labels are an array.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_jsonl<W: Write>(
+    mut writer: W,
+    traces: &[Trace],
+    columns: &[Column],
+    run_id: Option<&str>,
+) -> io::Result<()> {
+    //  An object for each test:
+    //
+    //      {"name":"test_one","number":1,"start_seconds":0.0,...,"run_id":null}
+    let columns = selected(columns);
+    for trace in traces {
+        // Written by hand, as a `serde_json::Map` would sort the keys
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|c| format!("{}:{}", Value::from(c.name()), c.value(trace, run_id)))
+            .collect();
+        writeln!(writer, "{{{}}}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::TestStatus;
+    use std::time::Duration;
+
+    fn traces() -> Vec<Trace> {
+        vec![
+            Trace::new("test_one", Duration::new(0, 0), Duration::from_millis(200))
+                .with_number(1)
+                .with_labels(vec!["db".into(), "slow".into()]),
+            Trace::new(
+                "test, \"two\"",
+                Duration::from_millis(200),
+                Duration::from_millis(1500),
+            )
+            .with_thread_number(1)
+            .with_status(TestStatus::Timeout),
+        ]
+    }
+
+    fn csv(columns: &[Column], run_id: Option<&str>) -> String {
+        let mut output = vec![];
+        write_csv(&mut output, &traces(), columns, run_id).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn jsonl(columns: &[Column], run_id: Option<&str>) -> String {
+        let mut output = vec![];
+        write_jsonl(&mut output, &traces(), columns, run_id).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_column_names() {
+        for column in Column::ALL.iter() {
+            assert_eq!(column.name().parse::<Column>(), Ok(*column));
+        }
+        assert!("dur".parse::<Column>().is_err());
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let expected = r#"name,number,start_seconds,duration_seconds,lane,status,labels,run_id
+test_one,1,0.0,0.2,0,Passed,db;slow,nightly
+"test, ""two""",,0.2,1.5,1,Timeout,,nightly
+"#;
+        assert_eq!(csv(&[], Some("nightly")), expected);
+    }
+
+    #[test]
+    fn test_selected_columns_keep_their_order() {
+        let expected = "name,duration_seconds\ntest_one,0.2\n\"test, \"\"two\"\"\",1.5\n";
+        assert_eq!(csv(&[Column::Duration, Column::Name], None), expected);
+    }
+
+    #[test]
+    fn test_write_jsonl() {
+        let expected = r#"{"name":"test_one","number":1,"start_seconds":0.0,"duration_seconds":0.2,"lane":0,"status":"Passed","labels":["db","slow"],"run_id":null}
+{"name":"test, \"two\"","number":null,"start_seconds":0.2,"duration_seconds":1.5,"lane":1,"status":"Timeout","labels":[],"run_id":null}
+"#;
+        assert_eq!(jsonl(&[], None), expected);
+
+        let expected =
+            "{\"status\":\"Passed\",\"run_id\":\"7\"}\n{\"status\":\"Timeout\",\"run_id\":\"7\"}\n";
+        assert_eq!(jsonl(&[Column::RunId, Column::Status], Some("7")), expected);
+    }
+}
@@ -0,0 +1,550 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::parser::Run;
+use crate::trace::{TestStatus, Trace};
+
+use regex::Regex;
+use std::io;
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+// The indentation of the YAML diagnostics under their test point
+const YAML_INDENT: &str = "  ";
+
+// The YAML diagnostics of a test point which say when and how the test ran,
+// any others are ignored
+#[derive(Debug, Default)]
+struct Diagnostics {
+    duration_ms: Option<f64>,
+    start_ms: Option<f64>,
+    lane: Option<u32>,
+    status: Option<String>,
+    message: Option<String>,
+}
+
+impl Diagnostics {
+    // Reads the diagnostics from the `key: value` lines of a YAML block, with
+    // its indentation removed.  Only flat scalars are needed, so nested
+    // values, lists, and keys which aren't needed are skipped over.  Returns
+    // the index of the invalid line along with the error.
+    fn parse(lines: &[&str]) -> Result<Diagnostics, (usize, String)> {
+        let mut diagnostics = Diagnostics::default();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index];
+            let at = index;
+            index += 1;
+            if line.starts_with(char::is_whitespace) || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+            // A block scalar, `|` or `>`, is the more indented lines after it
+            let value = if value.starts_with(['|', '>']) {
+                let block: Vec<&str> = lines[index..]
+                    .iter()
+                    .take_while(|l| l.trim().is_empty() || l.starts_with(char::is_whitespace))
+                    .map(|l| l.trim())
+                    .collect();
+                index += block.len();
+                let separator = if value.starts_with('|') { "\n" } else { " " };
+                block.join(separator).trim_end().to_string()
+            } else {
+                scalar(value).map_err(|e| (at, format!("{} {}", key, e)))?
+            };
+            let number = |value: &str| {
+                value
+                    .parse()
+                    .map_err(|_| (at, format!("{} isn't a number: {}", key, value)))
+            };
+            match key {
+                "duration_ms" => diagnostics.duration_ms = Some(number(&value)?),
+                "start_ms" => diagnostics.start_ms = Some(number(&value)?),
+                "lane" => {
+                    let lane = value
+                        .parse()
+                        .map_err(|_| (at, format!("lane isn't a lane number: {}", value)))?;
+                    diagnostics.lane = Some(lane);
+                }
+                "status" => diagnostics.status = Some(value),
+                "message" => diagnostics.message = Some(value),
+                _ => {}
+            }
+        }
+        Ok(diagnostics)
+    }
+}
+
+// The text of a YAML flow scalar, plain, single quoted, or double quoted as
+// written by `quote()`, without any comment after it
+fn scalar(value: &str) -> Result<String, String> {
+    let (text, rest) = match value.chars().next() {
+        Some('"') => {
+            // The closing quote is the first one which isn't escaped
+            let mut escaped = false;
+            let end = value
+                .char_indices()
+                .skip(1)
+                .find(|(_, c)| {
+                    let end = *c == '"' && !escaped;
+                    escaped = *c == '\\' && !escaped;
+                    end
+                })
+                .map(|(end, _)| end + 1)
+                .ok_or_else(|| format!("has an unterminated quoted string: {}", value))?;
+            let text = serde_json::from_str(&value[..end])
+                .map_err(|_| format!("has an invalid quoted string: {}", value))?;
+            (text, &value[end..])
+        }
+        Some('\'') => {
+            // A quote is escaped by doubling it
+            let mut quoted = &value[1..];
+            let mut text = String::new();
+            loop {
+                let end = quoted
+                    .find('\'')
+                    .ok_or_else(|| format!("has an unterminated quoted string: {}", value))?;
+                text.push_str(&quoted[..end]);
+                if !quoted[end + 1..].starts_with('\'') {
+                    break (text, &quoted[end + 1..]);
+                }
+                text.push('\'');
+                quoted = &quoted[end + 2..];
+            }
+        }
+        // A comment is a `#` after a space
+        _ => match value.find(" #") {
+            Some(comment) => (value[..comment].trim_end().to_string(), &value[comment..]),
+            None => (value.to_string(), ""),
+        },
+    };
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("has text after its quoted string: {}", value));
+    }
+    Ok(text)
+}
+
+// Escapes `text` for the description of a test point, which must stay on one
+// line and can't have a `#` other than the directive's
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('#', "\\#")
+        .replace(['\r', '\n'], " ")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+fn from_milliseconds(milliseconds: f64) -> Duration {
+    Duration::from_micros((milliseconds * 1000.0).round() as u64)
+}
+
+// Quotes `text` as a YAML scalar, JSON strings are also YAML
+fn quote(text: &str) -> String {
+    serde_json::Value::from(text).to_string()
+}
+
+/// Writes the tests of `run` as a TAP version 14 stream.
+///
+/// Each test is a test point, `ok` when it passed, was skipped, or wasn't run,
+/// and `not ok` otherwise.  Skipped tests and those not run have a `SKIP`
+/// directive.  The YAML diagnostics of each test point have the duration of
+/// the test, when it started, the lane it ran in, and its status as ctest
+/// reported it, so [`read_tap()`](read_tap) gets the same tests back.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_tap<W: Write>(mut writer: W, run: &Run) -> io::Result<()> {
+    //  The stream, with a test point for each test:
+    //
+    //      TAP version 14
+    //      1..2
+    //      ok 1 - test_one
+    //        ---
+    //        duration_ms: 200
+    //        start_ms: 0
+    //        lane: 0
+    //        status: "Passed"
+    //        ...
+    //      ok 2 - test_two # SKIP Skipped
+    //        ---
+    //        ...
+    writeln!(writer, "TAP version 14")?;
+    writeln!(writer, "1..{}", run.traces.len())?;
+    for (index, trace) in run.traces.iter().enumerate() {
+        let result = if trace.status.is_failure() {
+            "not ok"
+        } else {
+            "ok"
+        };
+        write!(writer, "{} {} - {}", result, index + 1, escape(&trace.name))?;
+        if matches!(trace.status, TestStatus::Skipped | TestStatus::NotRun) {
+            write!(writer, " # SKIP {}", trace.status)?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "  ---")?;
+        writeln!(writer, "  duration_ms: {}", milliseconds(trace.duration))?;
+        writeln!(writer, "  start_ms: {}", milliseconds(trace.start))?;
+        writeln!(writer, "  lane: {}", trace.thread_number)?;
+        writeln!(writer, "  status: {}", quote(&trace.status.to_string()))?;
+        writeln!(writer, "  ...")?;
+    }
+    Ok(())
+}
+
+/// Whether `content`, the start of the input, is a TAP stream, i.e. starts
+/// with the `TAP version` line of TAP 13 or later.
+pub fn is_tap(content: &[u8]) -> bool {
+    String::from_utf8_lossy(content)
+        .trim_start()
+        .starts_with("TAP version ")
+}
+
+// The directive of a test point, after its `#`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Directive {
+    None,
+    Skip,
+    Todo,
+}
+
+impl Directive {
+    fn parse(directive: &str) -> Directive {
+        let starts_with = |word: &str| {
+            directive
+                .get(..word.len())
+                .is_some_and(|d| d.eq_ignore_ascii_case(word))
+        };
+        if starts_with("skip") {
+            Directive::Skip
+        } else if starts_with("todo") {
+            Directive::Todo
+        } else {
+            Directive::None
+        }
+    }
+}
+
+// A test point waiting on the YAML diagnostics which may follow it
+struct TestPoint {
+    name: String,
+    ok: bool,
+    directive: Directive,
+    diagnostics: Diagnostics,
+}
+
+impl TestPoint {
+    fn into_trace(self, next_start: Duration) -> Trace {
+        let diagnostics = self.diagnostics;
+        // A failing `TODO` test is expected to fail, so isn't a failure
+        let status = match (diagnostics.status, self.ok, self.directive) {
+            (Some(status), _, _) => TestStatus::from_result(&status),
+            (None, _, Directive::Skip) | (None, false, Directive::Todo) => TestStatus::Skipped,
+            (None, true, _) => TestStatus::Passed,
+            (None, false, Directive::None) => {
+                TestStatus::Failed(diagnostics.message.unwrap_or_default())
+            }
+        };
+        Trace::new(
+            &self.name,
+            diagnostics.start_ms.map_or(next_start, from_milliseconds),
+            diagnostics
+                .duration_ms
+                .map_or_else(Duration::default, from_milliseconds),
+        )
+        .with_thread_number(diagnostics.lane.unwrap_or(0))
+        .with_status(status)
+    }
+}
+
+/// Reads the test points of a TAP stream as the tests of a [`Run`](Run).
+///
+/// The times of the tests come from the `duration_ms` of their YAML
+/// diagnostics, along with the `start_ms`, `lane`, and `status` written by
+/// [`write_tap()`](write_tap).  Without a `start_ms` a test starts when the
+/// one before it finished, as if they ran one at a time.  Without a `status`
+/// a test passed when `ok`, was skipped with a `SKIP` directive, or when
+/// `not ok` with a `TODO` directive, and otherwise failed, with the `message`
+/// of its diagnostics as the reason.
+///
+/// Only the flat `key: value` lines of the YAML diagnostics are read, which
+/// must be indented two spaces under their test point.  The plan, comments,
+/// pragmas, and subtests, along with their diagnostics, are skipped over.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when there is a failure to read the
+/// lines of `reader`, or the diagnostics a test point needs are invalid.
+pub fn read_tap<R: BufRead>(reader: R) -> io::Result<Run> {
+    //  A test point, with optional YAML diagnostics indented under it:
+    //
+    //      not ok 2 - test_two # SKIP reason
+    //        ---
+    //        duration_ms: 300
+    //        ...
+    let test_point =
+        Regex::new(r"^(not )?ok\b(?:\s*\d+)?(?:\s*-)?\s*(?P<rest>.*)$").expect("valid regex");
+    let directive = Regex::new(r"(?:^|[^\\])(?:\\\\)*(?P<hash>#)").expect("valid regex");
+
+    let mut traces = vec![];
+    let mut pending: Option<TestPoint> = None;
+    // The lines of the YAML diagnostics being read, and the number of the
+    // line before them
+    let mut yaml: Option<(usize, Vec<String>)> = None;
+    let mut next_start = Duration::default();
+    let mut finish = |pending: &mut Option<TestPoint>, traces: &mut Vec<Trace>| {
+        if let Some(point) = pending.take() {
+            let trace = point.into_trace(next_start);
+            next_start = trace.start + trace.duration;
+            traces.push(trace);
+        }
+    };
+    let yaml_start = format!("{}---", YAML_INDENT);
+    let yaml_end = format!("{}...", YAML_INDENT);
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if let Some((start, lines)) = &mut yaml {
+            if line.trim_end() != yaml_end {
+                lines.push(line);
+                continue;
+            }
+            let block: Vec<&str> = lines
+                .iter()
+                .map(|l| l.strip_prefix(YAML_INDENT).unwrap_or(l.trim_start()))
+                .collect();
+            if let Some(point) = &mut pending {
+                point.diagnostics = Diagnostics::parse(&block).map_err(|(at, e)| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Line {} of the TAP has invalid YAML diagnostics: {}",
+                            *start + at + 1,
+                            e
+                        ),
+                    )
+                })?;
+            }
+            yaml = None;
+            finish(&mut pending, &mut traces);
+        } else if pending.is_some() && line.trim_end() == yaml_start {
+            yaml = Some((index + 1, vec![]));
+        } else if let Some(captures) = test_point.captures(&line) {
+            finish(&mut pending, &mut traces);
+            let rest = &captures["rest"];
+            let (description, directive) = match directive.captures(rest) {
+                Some(c) => {
+                    let hash = c.name("hash").unwrap().start();
+                    (&rest[..hash], rest[hash + 1..].trim())
+                }
+                None => (rest, ""),
+            };
+            pending = Some(TestPoint {
+                name: unescape(description.trim()),
+                ok: captures.get(1).is_none(),
+                directive: Directive::parse(directive),
+                diagnostics: Diagnostics::default(),
+            });
+        }
+    }
+    finish(&mut pending, &mut traces);
+
+    Ok(Run {
+        traces,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    fn run() -> Run {
+        Run {
+            traces: vec![
+                Trace::new("passes", Duration::new(0, 0), Duration::from_millis(200)),
+                Trace::new("fails #1", Duration::new(0, 0), Duration::from_millis(300))
+                    .with_thread_number(1)
+                    .with_status(TestStatus::Failed("Required \"x\"".into())),
+                Trace::new(
+                    "crashes",
+                    Duration::from_millis(200),
+                    Duration::from_millis(10),
+                )
+                .with_status(TestStatus::Exception("SegFault".into())),
+                Trace::new("skips", Duration::from_millis(300), Duration::new(0, 0))
+                    .with_thread_number(1)
+                    .with_status(TestStatus::Skipped),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn read(tap: &str) -> Run {
+        read_tap(BufReader::new(tap.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_write_tap() {
+        let mut output = vec![];
+        write_tap(&mut output, &run()).unwrap();
+        let expected = r#"TAP version 14
+1..4
+ok 1 - passes
+  ---
+  duration_ms: 200
+  start_ms: 0
+  lane: 0
+  status: "Passed"
+  ...
+not ok 2 - fails \#1
+  ---
+  duration_ms: 300
+  start_ms: 0
+  lane: 1
+  status: "Failed  Required \"x\""
+  ...
+not ok 3 - crashes
+  ---
+  duration_ms: 10
+  start_ms: 200
+  lane: 0
+  status: "Exception: SegFault"
+  ...
+ok 4 - skips # SKIP Skipped
+  ---
+  duration_ms: 0
+  start_ms: 300
+  lane: 1
+  status: "Skipped"
+  ...
+"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut output = vec![];
+        write_tap(&mut output, &run()).unwrap();
+        assert_eq!(read_tap(BufReader::new(&output[..])).unwrap(), run());
+    }
+
+    #[test]
+    fn test_read_other_producers() {
+        let tap = r#"TAP version 14
+# a comment
+1..6
+ok 1 - first
+  ---
+  duration_ms: 12.5
+  at:
+    file: test.js
+    line: 3
+  tags:
+  - slow
+  ...
+not ok 2 second
+  ---
+  message: 'expected 1, it''s 2' # the assertion
+  duration_ms: 100
+  ...
+    # Subtest: nested
+    ok 1 - ignored
+      ---
+      duration_ms: 5000
+      ...
+    1..1
+ok 3 - third # skip no network
+ok - \# fourth \\
+not ok 5 - fifth # TODO later
+ok 6 - sixth # todo fixed already
+"#;
+        let after_second = Duration::from_micros(112500);
+        let expected = vec![
+            Trace::new("first", Duration::new(0, 0), Duration::from_micros(12500)),
+            Trace::new(
+                "second",
+                Duration::from_micros(12500),
+                Duration::from_millis(100),
+            )
+            .with_status(TestStatus::Failed("expected 1, it's 2".into())),
+            Trace::new("third", after_second, Duration::new(0, 0)).with_status(TestStatus::Skipped),
+            Trace::new("# fourth \\", after_second, Duration::new(0, 0)),
+            Trace::new("fifth", after_second, Duration::new(0, 0)).with_status(TestStatus::Skipped),
+            Trace::new("sixth", after_second, Duration::new(0, 0)),
+        ];
+        let run = read(tap);
+        assert_eq!(run.traces, expected);
+    }
+
+    #[test]
+    fn test_read_block_scalar_message() {
+        let tap = "TAP version 14\nnot ok 1 - a\n  ---\n  message: |\n    expected 1\n    got 2\n  duration_ms: 5\n  ...\n";
+        let run = read(tap);
+        assert_eq!(
+            run.traces[0].status,
+            TestStatus::Failed("expected 1\ngot 2".into())
+        );
+        assert_eq!(run.traces[0].duration, Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_scalar() {
+        assert_eq!(scalar("plain text # comment"), Ok("plain text".into()));
+        assert_eq!(scalar("a#b"), Ok("a#b".into()));
+        assert_eq!(scalar(r#""say \"hi\"""#), Ok("say \"hi\"".into()));
+        assert_eq!(scalar("'it''s'"), Ok("it's".into()));
+        assert_eq!(scalar("'a ' # comment"), Ok("a ".into()));
+        assert_eq!(scalar(r#""a \\" # comment"#), Ok("a \\".into()));
+        assert!(scalar("'open").is_err());
+        assert!(scalar("'a' b").is_err());
+        assert!(scalar("\"open").is_err());
+    }
+
+    #[test]
+    fn test_sequential_without_start() {
+        let tap = "TAP version 14\nok 1 - a\n  ---\n  duration_ms: 100\n  ...\nok 2 - b\n  ---\n  duration_ms: 50\n  ...\n";
+        let run = read(tap);
+        assert_eq!(run.traces[1].start, Duration::from_millis(100));
+        assert_eq!(run.wall_time(), Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_invalid_diagnostics() {
+        let tap = "TAP version 14\nok 1 - a\n  ---\n  lane: 0\n  duration_ms: [1\n  ...\n";
+        let error = read_tap(BufReader::new(tap.as_bytes())).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "Line 5 of the TAP has invalid YAML diagnostics: duration_ms isn't a number: [1"
+        );
+    }
+
+    #[test]
+    fn test_is_tap() {
+        assert!(is_tap(b"\nTAP version 14\n1..2"));
+        assert!(!is_tap(b"Start 1: test_one"));
+        assert!(!is_tap(b"[{\"ph\": \"X\"}]"));
+    }
+}
@@ -0,0 +1,195 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use crate::trace::{TestStatus, Trace};
+
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+
+// Escapes `text` for a value of a service message
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '|' => escaped.push_str("||"),
+            '\'' => escaped.push_str("|'"),
+            '\n' => escaped.push_str("|n"),
+            '\r' => escaped.push_str("|r"),
+            '[' => escaped.push_str("|["),
+            ']' => escaped.push_str("|]"),
+            '\u{85}' | '\u{2028}' | '\u{2029}' => escaped.push_str(&format!("|0x{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// The message of a test which didn't pass
+fn failure_message(status: &TestStatus) -> String {
+    match status {
+        TestStatus::Failed(reason) if !reason.is_empty() => reason.clone(),
+        status => status.to_string(),
+    }
+}
+
+// A point in the run where a test starts or finishes, or where a test which
+// never ran was ignored
+enum Step<'a> {
+    Finish(&'a Trace),
+    Start(&'a Trace),
+    Ignore(&'a Trace),
+}
+
+/// Writes `traces` as TeamCity service messages, as if the tests were
+/// reported as they ran.
+///
+/// Each test's messages have the `flowId` of the lane, thread, it ran in, so
+/// TeamCity can tell the tests running in parallel apart.  The messages are
+/// in the order the tests started and finished, with tests finishing before
+/// others start at the same time.  Skipped tests, which never ran, only get
+/// a `testIgnored`.
+///
+/// # Errors
+///
+/// Returns [`io::Error`](io::Error) when `writer` fails.
+pub fn write_service_messages<W: Write>(mut writer: W, traces: &[Trace]) -> io::Result<()> {
+    //  The messages of a test which ran, with `testFailed` when it didn't
+    //  pass, and the duration in milliseconds:
+    //
+    //      ##teamcity[testStarted name='string' flowId='int']
+    //      ##teamcity[testFailed name='string' message='string' flowId='int']
+    //      ##teamcity[testFinished name='string' duration='int' flowId='int']
+    //
+    //  A test which never ran is only ignored:
+    //
+    //      ##teamcity[testIgnored name='string' message='string' flowId='int']
+    let mut steps: Vec<(Duration, Step)> = traces
+        .iter()
+        .flat_map(|t| match t.status {
+            TestStatus::Skipped | TestStatus::NotRun => vec![(t.start, Step::Ignore(t))],
+            _ => vec![
+                (t.start, Step::Start(t)),
+                (t.start + t.duration, Step::Finish(t)),
+            ],
+        })
+        .collect();
+    // A test which took no time still has to start before it finishes
+    steps.sort_by_key(|(time, step)| {
+        let order = match step {
+            Step::Finish(trace) if !trace.duration.is_zero() => 0,
+            Step::Start(_) | Step::Ignore(_) => 1,
+            Step::Finish(_) => 2,
+        };
+        (*time, order)
+    });
+
+    for (_, step) in steps {
+        match step {
+            Step::Start(trace) => writeln!(
+                writer,
+                "##teamcity[testStarted name='{}' flowId='{}']",
+                escape(&trace.name),
+                trace.thread_number
+            )?,
+            Step::Ignore(trace) => writeln!(
+                writer,
+                "##teamcity[testIgnored name='{}' message='{}' flowId='{}']",
+                escape(&trace.name),
+                escape(&trace.status.to_string()),
+                trace.thread_number
+            )?,
+            Step::Finish(trace) => {
+                let name = escape(&trace.name);
+                let flow = trace.thread_number;
+                if trace.status != TestStatus::Passed {
+                    writeln!(
+                        writer,
+                        "##teamcity[testFailed name='{}' message='{}' flowId='{}']",
+                        name,
+                        escape(&failure_message(&trace.status)),
+                        flow
+                    )?;
+                }
+                writeln!(
+                    writer,
+                    "##teamcity[testFinished name='{}' duration='{}' flowId='{}']",
+                    name,
+                    trace.duration.as_millis(),
+                    flow
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(traces: &[Trace]) -> String {
+        let mut output = vec![];
+        write_service_messages(&mut output, traces).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("it's [a|b]\r\n\u{2028}"),
+            "it|'s |[a||b|]|r|n|0x2028"
+        );
+    }
+
+    #[test]
+    fn test_parallel_tests() {
+        let traces = vec![
+            Trace::new("one", Duration::new(0, 0), Duration::from_millis(200)),
+            Trace::new("two", Duration::new(0, 0), Duration::from_millis(300))
+                .with_thread_number(1)
+                .with_status(TestStatus::Timeout),
+            Trace::new(
+                "three",
+                Duration::from_millis(200),
+                Duration::from_millis(50),
+            )
+            .with_status(TestStatus::Skipped),
+        ];
+        let expected = r#"##teamcity[testStarted name='one' flowId='0']
+##teamcity[testStarted name='two' flowId='1']
+##teamcity[testFinished name='one' duration='200' flowId='0']
+##teamcity[testIgnored name='three' message='Skipped' flowId='0']
+##teamcity[testFailed name='two' message='Timeout' flowId='1']
+##teamcity[testFinished name='two' duration='300' flowId='1']
+"#;
+        assert_eq!(messages(&traces), expected);
+    }
+
+    #[test]
+    fn test_failure_messages() {
+        let traces = vec![
+            Trace::new("a", Duration::new(0, 0), Duration::new(0, 0))
+                .with_status(TestStatus::Failed("".into())),
+            Trace::new("b", Duration::new(0, 0), Duration::new(0, 0))
+                .with_thread_number(1)
+                .with_status(TestStatus::Failed("Required [x]".into())),
+            Trace::new("c", Duration::new(0, 0), Duration::new(0, 0))
+                .with_thread_number(2)
+                .with_status(TestStatus::Exception("SegFault".into())),
+        ];
+        let expected = r#"##teamcity[testStarted name='a' flowId='0']
+##teamcity[testStarted name='b' flowId='1']
+##teamcity[testStarted name='c' flowId='2']
+##teamcity[testFailed name='a' message='Failed' flowId='0']
+##teamcity[testFinished name='a' duration='0' flowId='0']
+##teamcity[testFailed name='b' message='Required |[x|]' flowId='1']
+##teamcity[testFinished name='b' duration='0' flowId='1']
+##teamcity[testFailed name='c' message='Exception: SegFault' flowId='2']
+##teamcity[testFinished name='c' duration='0' flowId='2']
+"#;
+        assert_eq!(messages(&traces), expected);
+    }
+}
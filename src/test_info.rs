@@ -0,0 +1,395 @@
+//          Copyright Nick G 2021.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::time::Duration;
+
+/// The properties of a single test, as configured in CMake.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestProperties {
+    pub depends: Vec<String>,
+    pub fixtures_setup: Vec<String>,
+    pub fixtures_required: Vec<String>,
+    pub fixtures_cleanup: Vec<String>,
+    pub processors: Option<u32>,
+    pub timeout: Option<Duration>,
+    pub labels: Vec<String>,
+}
+
+/// A test which must finish before another test may start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prerequisite {
+    /// The name of the test which must finish first
+    pub name: String,
+    /// Why the test must finish first, either `DEPENDS` or the name of the
+    /// fixture
+    pub reason: String,
+}
+
+/// The properties of all the tests in a ctest project.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestInfo {
+    tests: HashMap<String, TestProperties>,
+}
+
+//  The subset of `ctest --show-only=json-v1` which is needed, see
+//  https://cmake.org/cmake/help/latest/manual/ctest.1.html#show-as-json-object-model
+#[derive(Deserialize)]
+struct JsonV1 {
+    #[serde(default)]
+    tests: Vec<JsonV1Test>,
+}
+
+#[derive(Deserialize)]
+struct JsonV1Test {
+    name: String,
+    #[serde(default)]
+    properties: Vec<JsonV1Property>,
+}
+
+#[derive(Deserialize)]
+struct JsonV1Property {
+    name: String,
+    value: Value,
+}
+
+// List properties are arrays in json-v1, but be lenient and accept a CMake
+// style `;` separated string as well.
+fn string_list(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(values) => values
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(String::from)
+            .collect(),
+        Value::String(s) => s
+            .split(';')
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+impl TestInfo {
+    /// Reads the output of `ctest --show-only=json-v1`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`serde_json::Error`](serde_json::Error) when the `reader` does
+    /// not contain valid json-v1 content.
+    pub fn from_json_v1<R: Read>(reader: R) -> Result<TestInfo, serde_json::Error> {
+        let json: JsonV1 = serde_json::from_reader(reader)?;
+        let mut tests = HashMap::new();
+        for test in json.tests {
+            let mut properties = TestProperties::default();
+            for property in &test.properties {
+                match property.name.as_str() {
+                    "DEPENDS" => properties.depends = string_list(&property.value),
+                    "FIXTURES_SETUP" => properties.fixtures_setup = string_list(&property.value),
+                    "FIXTURES_REQUIRED" => {
+                        properties.fixtures_required = string_list(&property.value)
+                    }
+                    "FIXTURES_CLEANUP" => {
+                        properties.fixtures_cleanup = string_list(&property.value)
+                    }
+                    "PROCESSORS" => {
+                        properties.processors = property.value.as_u64().map(|p| p as u32)
+                    }
+                    "LABELS" => properties.labels = string_list(&property.value),
+                    "TIMEOUT" => {
+                        properties.timeout = property
+                            .value
+                            .as_f64()
+                            .filter(|t| *t > 0.0)
+                            .and_then(|t| Duration::try_from_secs_f64(t).ok())
+                    }
+                    _ => {}
+                }
+            }
+            tests.insert(test.name, properties);
+        }
+        Ok(TestInfo { tests })
+    }
+
+    /// Adds, or replaces, the properties for the test `name`.
+    pub fn insert(&mut self, name: &str, properties: TestProperties) {
+        self.tests.insert(name.to_string(), properties);
+    }
+
+    /// The properties of the test `name`, if known.
+    pub fn get(&self, name: &str) -> Option<&TestProperties> {
+        self.tests.get(name)
+    }
+
+    /// Sets the `PROCESSORS` property of the test `name`, adding the test if
+    /// it isn't known.
+    pub fn set_processors(&mut self, name: &str, processors: u32) {
+        self.tests.entry(name.to_string()).or_default().processors = Some(processors);
+    }
+
+    /// The number of processors the test `name` uses.  Tests without a
+    /// `PROCESSORS` property use 1, as does ctest.
+    pub fn processors(&self, name: &str) -> u32 {
+        self.get(name)
+            .and_then(|p| p.processors)
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    /// The `TIMEOUT` property of the test `name`, if it has one.
+    pub fn timeout(&self, name: &str) -> Option<Duration> {
+        self.get(name).and_then(|p| p.timeout)
+    }
+
+    /// The `LABELS` property of the test `name`, empty if it has none.
+    pub fn labels(&self, name: &str) -> &[String] {
+        self.get(name).map_or(&[], |p| &p.labels)
+    }
+
+    /// The tests which must finish before the test `name` may start.
+    ///
+    /// This is the `DEPENDS` property of the test, the setup tests of any
+    /// fixtures it requires, and for a cleanup test, the setup tests and the
+    /// tests requiring the fixture being cleaned up.
+    pub fn prerequisites(&self, name: &str) -> Vec<Prerequisite> {
+        let properties = match self.get(name) {
+            Some(properties) => properties,
+            None => return vec![],
+        };
+
+        let mut prerequisites: Vec<Prerequisite> = properties
+            .depends
+            .iter()
+            .map(|depend| Prerequisite {
+                name: depend.clone(),
+                reason: "DEPENDS".into(),
+            })
+            .collect();
+
+        // Sorted so the results don't depend on the `HashMap` ordering
+        let mut others: Vec<(&String, &TestProperties)> = self
+            .tests
+            .iter()
+            .filter(|(other, _)| *other != name)
+            .collect();
+        others.sort_by(|a, b| a.0.cmp(b.0));
+
+        for fixture in &properties.fixtures_required {
+            for (other, other_properties) in &others {
+                if other_properties.fixtures_setup.contains(fixture) {
+                    prerequisites.push(Prerequisite {
+                        name: other.to_string(),
+                        reason: fixture.clone(),
+                    });
+                }
+            }
+        }
+
+        for fixture in &properties.fixtures_cleanup {
+            for (other, other_properties) in &others {
+                if other_properties.fixtures_setup.contains(fixture)
+                    || other_properties.fixtures_required.contains(fixture)
+                {
+                    prerequisites.push(Prerequisite {
+                        name: other.to_string(),
+                        reason: fixture.clone(),
+                    });
+                }
+            }
+        }
+
+        // A test reached both through `DEPENDS` and a fixture, or through
+        // several fixtures, only needs to finish first once
+        let mut seen = HashSet::new();
+        prerequisites.retain(|prerequisite| seen.insert(prerequisite.name.clone()));
+        prerequisites
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON_V1: &str = r#"{
+        "kind": "ctestInfo",
+        "version": {"major": 1, "minor": 0},
+        "tests": [
+            {
+                "name": "db_setup",
+                "properties": [
+                    {"name": "FIXTURES_SETUP", "value": ["db"]}
+                ]
+            },
+            {
+                "name": "query",
+                "properties": [
+                    {"name": "FIXTURES_REQUIRED", "value": ["db"]},
+                    {"name": "DEPENDS", "value": ["compile"]}
+                ]
+            },
+            {
+                "name": "insert",
+                "properties": [
+                    {"name": "FIXTURES_REQUIRED", "value": "db"},
+                    {"name": "PROCESSORS", "value": 4},
+                    {"name": "TIMEOUT", "value": 1.5},
+                    {"name": "LABELS", "value": ["db", "slow"]}
+                ]
+            },
+            {
+                "name": "db_cleanup",
+                "properties": [
+                    {"name": "FIXTURES_CLEANUP", "value": ["db"]}
+                ]
+            },
+            {
+                "name": "compile"
+            }
+        ]
+    }"#;
+
+    fn prerequisite(name: &str, reason: &str) -> Prerequisite {
+        Prerequisite {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+
+    #[test]
+    fn test_read_json_v1_properties() {
+        let info = TestInfo::from_json_v1(JSON_V1.as_bytes()).unwrap();
+        let expected = TestProperties {
+            depends: vec!["compile".into()],
+            fixtures_required: vec!["db".into()],
+            ..Default::default()
+        };
+        assert_eq!(info.get("query"), Some(&expected));
+        assert_eq!(info.get("compile"), Some(&TestProperties::default()));
+        assert_eq!(info.get("not_a_test"), None);
+    }
+
+    #[test]
+    fn test_read_json_v1_processors() {
+        let info = TestInfo::from_json_v1(JSON_V1.as_bytes()).unwrap();
+        assert_eq!(info.processors("insert"), 4);
+        assert_eq!(info.processors("query"), 1);
+        assert_eq!(info.processors("not_a_test"), 1);
+    }
+
+    #[test]
+    fn test_set_processors() {
+        let mut info = TestInfo::from_json_v1(JSON_V1.as_bytes()).unwrap();
+        info.set_processors("query", 2);
+        info.set_processors("not_a_test", 3);
+        assert_eq!(info.processors("query"), 2);
+        assert_eq!(info.processors("not_a_test"), 3);
+        assert_eq!(info.get("query").unwrap().depends, vec!["compile"]);
+    }
+
+    #[test]
+    fn test_read_json_v1_timeout() {
+        let info = TestInfo::from_json_v1(JSON_V1.as_bytes()).unwrap();
+        assert_eq!(info.timeout("insert"), Some(Duration::from_millis(1500)));
+        assert_eq!(info.timeout("query"), None);
+        assert_eq!(info.timeout("not_a_test"), None);
+    }
+
+    #[test]
+    fn test_read_json_v1_timeout_out_of_range() {
+        let json = r#"{
+            "kind": "ctestInfo",
+            "version": {"major": 1, "minor": 0},
+            "tests": [
+                {
+                    "name": "forever",
+                    "properties": [{"name": "TIMEOUT", "value": 1e30}]
+                }
+            ]
+        }"#;
+        let info = TestInfo::from_json_v1(json.as_bytes()).unwrap();
+        assert_eq!(info.timeout("forever"), None);
+    }
+
+    #[test]
+    fn test_read_json_v1_labels() {
+        let info = TestInfo::from_json_v1(JSON_V1.as_bytes()).unwrap();
+        assert_eq!(info.labels("insert"), ["db", "slow"]);
+        assert!(info.labels("query").is_empty());
+        assert!(info.labels("not_a_test").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_json_v1() {
+        assert!(TestInfo::from_json_v1("not json".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_prerequisites_of_fixture_user() {
+        let info = TestInfo::from_json_v1(JSON_V1.as_bytes()).unwrap();
+        assert_eq!(
+            info.prerequisites("query"),
+            vec![
+                prerequisite("compile", "DEPENDS"),
+                prerequisite("db_setup", "db")
+            ]
+        );
+        assert_eq!(
+            info.prerequisites("insert"),
+            vec![prerequisite("db_setup", "db")]
+        );
+    }
+
+    #[test]
+    fn test_prerequisites_of_fixture_cleanup() {
+        let info = TestInfo::from_json_v1(JSON_V1.as_bytes()).unwrap();
+        assert_eq!(
+            info.prerequisites("db_cleanup"),
+            vec![
+                prerequisite("db_setup", "db"),
+                prerequisite("insert", "db"),
+                prerequisite("query", "db")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prerequisite_reached_twice() {
+        let json = r#"{
+            "kind": "ctestInfo",
+            "version": {"major": 1, "minor": 0},
+            "tests": [
+                {
+                    "name": "db_setup",
+                    "properties": [
+                        {"name": "FIXTURES_SETUP", "value": ["db", "cache"]}
+                    ]
+                },
+                {
+                    "name": "query",
+                    "properties": [
+                        {"name": "FIXTURES_REQUIRED", "value": ["db", "cache"]},
+                        {"name": "DEPENDS", "value": ["db_setup"]}
+                    ]
+                }
+            ]
+        }"#;
+        let info = TestInfo::from_json_v1(json.as_bytes()).unwrap();
+        assert_eq!(
+            info.prerequisites("query"),
+            vec![prerequisite("db_setup", "DEPENDS")]
+        );
+    }
+
+    #[test]
+    fn test_no_prerequisites() {
+        let info = TestInfo::from_json_v1(JSON_V1.as_bytes()).unwrap();
+        assert_eq!(info.prerequisites("db_setup"), vec![]);
+        assert_eq!(info.prerequisites("not_a_test"), vec![]);
+    }
+}
@@ -3,17 +3,218 @@
 //    (See accompanying file LICENSE or copy at
 //          https://www.boost.org/LICENSE_1_0.txt)
 
+use crate::outlier::Outlier;
+use crate::resources::ResourceAllocation;
+
 use serde::ser::SerializeMap;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
 use std::time::Duration;
 
+/// How a test finished, as reported by ctest.
+#[derive(PartialEq, Debug, Default, Clone)]
+#[non_exhaustive]
+pub enum TestStatus {
+    #[default]
+    Passed,
+    /// The test failed, with ctest's reason when it gave one, i.e. "Required
+    /// regular expression not found."
+    Failed(String),
+    /// The test ran longer than its `TIMEOUT`
+    Timeout,
+    /// The test asked to be skipped, see ctest's `SKIP_RETURN_CODE`
+    Skipped,
+    NotRun,
+    /// The test crashed, with the kind of exception, i.e. "SegFault"
+    Exception(String),
+}
+
+impl TestStatus {
+    /// Determines the status from the result ctest printed for a test, i.e.
+    /// "Passed", "***Failed", or "Exception: SegFault".
+    ///
+    /// Results which aren't known are treated as failures, with the result as
+    /// the reason.
+    pub fn from_result(result: &str) -> TestStatus {
+        let result = result
+            .trim_start_matches(|c: char| c == '.' || c == '*' || c.is_whitespace())
+            .trim_end();
+        // The text after a known result, if `result` starts with it
+        let after = |known: &str| {
+            let prefix = result.get(..known.len())?;
+            if prefix.eq_ignore_ascii_case(known) {
+                Some(result[known.len()..].trim())
+            } else {
+                None
+            }
+        };
+        if after("Passed") == Some("") {
+            TestStatus::Passed
+        } else if after("Timeout") == Some("") {
+            TestStatus::Timeout
+        } else if after("Skipped") == Some("") {
+            TestStatus::Skipped
+        } else if after("Not Run").is_some() {
+            TestStatus::NotRun
+        } else if let Some(kind) = after("Exception:") {
+            TestStatus::Exception(kind.to_string())
+        } else if let Some(reason) = after("Failed") {
+            TestStatus::Failed(reason.to_string())
+        } else {
+            TestStatus::Failed(result.to_string())
+        }
+    }
+
+    /// Whether the test failed, timed out, or crashed.
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            TestStatus::Failed(_) | TestStatus::Timeout | TestStatus::Exception(_)
+        )
+    }
+}
+
+impl fmt::Display for TestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestStatus::Passed => write!(f, "Passed"),
+            TestStatus::Failed(reason) if reason.is_empty() => write!(f, "Failed"),
+            TestStatus::Failed(reason) => write!(f, "Failed  {}", reason),
+            TestStatus::Timeout => write!(f, "Timeout"),
+            TestStatus::Skipped => write!(f, "Skipped"),
+            TestStatus::NotRun => write!(f, "Not Run"),
+            TestStatus::Exception(kind) => write!(f, "Exception: {}", kind),
+        }
+    }
+}
+
 /// represents a trace object
-#[derive(PartialEq, Debug)]
+///
+/// A trace serializes as a complete, `"X"`, event of the Chrome trace event
+/// format, and can be deserialized from one.  Only the `name`, `start`,
+/// `duration`, `thread_number`, `status`, and `outlier` survive the round
+/// trip.  The `number`, `extra_threads`, `resources`, and `labels` aren't part
+/// of the event, the other threads of a test using more than one processor are
+/// separate events, see [`per_thread()`](Trace::per_thread), so they're left
+/// empty in a deserialized trace.
+///
+/// New fields may be added, so outside this crate traces are made with
+/// [`new()`](Trace::new) and the `with_*` methods.
+#[derive(PartialEq, Debug, Default, Clone, Deserialize)]
+#[serde(try_from = "CompleteEvent")]
+#[non_exhaustive]
 pub struct Trace {
     pub name: String,
+    /// The number ctest gave the test, when known
+    pub number: Option<u32>,
     pub start: Duration,
     pub duration: Duration,
     pub thread_number: u32,
+    /// The other threads held by a test which uses more than one processor,
+    /// see ctest's `PROCESSORS` test property
+    pub extra_threads: Vec<u32>,
+    /// The resources ctest gave the test, when run with a resource spec file
+    pub resources: Vec<ResourceAllocation>,
+    /// How the test finished
+    pub status: TestStatus,
+    /// The test's `LABELS` property, when known from the test info
+    pub labels: Vec<String>,
+    /// How much slower the test was than its baseline, when it was
+    /// unusually slow, see [`Baseline`](crate::outlier::Baseline)
+    pub outlier: Option<Outlier>,
+}
+
+impl Trace {
+    /// Creates a trace of the test `name`, which passed, on thread `0`.
+    pub fn new(name: &str, start: Duration, duration: Duration) -> Trace {
+        Trace {
+            name: name.to_string(),
+            start,
+            duration,
+            ..Default::default()
+        }
+    }
+
+    /// This trace with the number ctest gave the test.
+    pub fn with_number(mut self, number: u32) -> Trace {
+        self.number = Some(number);
+        self
+    }
+
+    /// This trace on the thread `thread_number`.
+    pub fn with_thread_number(mut self, thread_number: u32) -> Trace {
+        self.thread_number = thread_number;
+        self
+    }
+
+    /// This trace with the test finishing as `status`.
+    pub fn with_status(mut self, status: TestStatus) -> Trace {
+        self.status = status;
+        self
+    }
+
+    /// This trace with the test's `LABELS` property.
+    pub fn with_labels(mut self, labels: Vec<String>) -> Trace {
+        self.labels = labels;
+        self
+    }
+
+    /// The number of processors, i.e. threads, this trace held.
+    pub fn processors(&self) -> usize {
+        1 + self.extra_threads.len()
+    }
+
+    /// A copy of this trace for each of the threads it held, with the
+    /// primary thread first.
+    pub fn per_thread(&self) -> Vec<Trace> {
+        let mut traces = vec![self.clone()];
+        for thread in &self.extra_threads {
+            let mut trace = self.clone();
+            trace.thread_number = *thread;
+            traces.push(trace);
+        }
+        traces
+    }
+}
+
+// The "args" of a trace's event, only those which apply are included
+struct Args<'a> {
+    processors: Option<usize>,
+    status: Option<&'a TestStatus>,
+    outlier: Option<&'a Outlier>,
+}
+
+impl Args<'_> {
+    fn is_empty(&self) -> bool {
+        self.processors.is_none() && self.status.is_none() && self.outlier.is_none()
+    }
+}
+
+impl Serialize for Args<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let length = self.processors.iter().count()
+            + self.status.iter().count()
+            + 2 * self.outlier.iter().count();
+        let mut map = serializer.serialize_map(Some(length))?;
+        if let Some(processors) = &self.processors {
+            map.serialize_entry("processors", processors)?;
+        }
+        if let Some(status) = &self.status {
+            map.serialize_entry("status", &status.to_string())?;
+        }
+        if let Some(outlier) = &self.outlier {
+            map.serialize_entry("outlier_score", &outlier.score)?;
+            map.serialize_entry(
+                "baseline_median_seconds",
+                &outlier.baseline_median.as_secs_f64(),
+            )?;
+        }
+        map.end()
+    }
 }
 
 impl Serialize for Trace {
@@ -30,11 +231,27 @@ impl Serialize for Trace {
     //         "pid": int,
     //         "tid": int
     //     }
+    //
+    //  Tests using more than one processor, or which didn't pass, also get:
+    //
+    //         "args": {"processors": int, "status": "string"}
+    //
+    //  Outliers against a baseline are colored red, with the reserved color
+    //  name of the trace viewers, and get the score in the args:
+    //
+    //         "cname": "terrible",
+    //         "args": {"outlier_score": float, "baseline_median_seconds": float}
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut map = serializer.serialize_map(Some(8))?;
+        let args = Args {
+            processors: Some(self.processors()).filter(|_| !self.extra_threads.is_empty()),
+            status: Some(&self.status).filter(|s| **s != TestStatus::Passed),
+            outlier: self.outlier.as_ref(),
+        };
+        let length = 8 + usize::from(!args.is_empty()) + self.outlier.iter().count();
+        let mut map = serializer.serialize_map(Some(length))?;
         map.serialize_entry("name", &self.name)?;
         map.serialize_entry("cat", "test")?;
         map.serialize_entry("ph", "X")?;
@@ -44,14 +261,131 @@ impl Serialize for Trace {
         map.serialize_entry("dur", &duration)?;
         map.serialize_entry("pid", &0)?;
         map.serialize_entry("tid", &self.thread_number)?;
+        if self.outlier.is_some() {
+            map.serialize_entry("cname", "terrible")?;
+        }
+        if !args.is_empty() {
+            map.serialize_entry("args", &args)?;
+        }
         map.end()
     }
 }
 
+//  The fields of a complete event needed for a trace, timestamps are in
+//  microseconds and may have fractions
+#[derive(Deserialize)]
+struct CompleteEvent {
+    name: String,
+    ph: String,
+    ts: f64,
+    #[serde(default)]
+    dur: f64,
+    #[serde(default)]
+    tid: u32,
+    #[serde(default)]
+    args: Option<EventArgs>,
+}
+
+#[derive(Deserialize)]
+struct EventArgs {
+    status: Option<String>,
+    outlier_score: Option<f64>,
+    baseline_median_seconds: Option<f64>,
+}
+
+// Converts microseconds to a duration, negative times become 0
+fn from_micros(micros: f64) -> Duration {
+    Duration::from_nanos((micros * 1000.0).round().max(0.0) as u64)
+}
+
+impl TryFrom<CompleteEvent> for Trace {
+    type Error = String;
+
+    fn try_from(event: CompleteEvent) -> Result<Self, Self::Error> {
+        if event.ph != "X" {
+            return Err(format!(
+                "expected a complete event, \"ph\": \"X\", but got \"{}\"",
+                event.ph
+            ));
+        }
+        let args = event.args.unwrap_or(EventArgs {
+            status: None,
+            outlier_score: None,
+            baseline_median_seconds: None,
+        });
+        let outlier = match (args.outlier_score, args.baseline_median_seconds) {
+            (Some(score), Some(median)) => Some(Outlier {
+                score,
+                baseline_median: from_micros(median * 1_000_000.0),
+            }),
+            _ => None,
+        };
+        Ok(Trace {
+            name: event.name,
+            start: from_micros(event.ts),
+            duration: from_micros(event.dur),
+            thread_number: event.tid,
+            status: args
+                .status
+                .map(|s| TestStatus::from_result(&s))
+                .unwrap_or_default(),
+            outlier,
+            ..Default::default()
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_test::{assert_ser_tokens, Token};
+    use serde_test::{assert_de_tokens_error, assert_ser_tokens, Token};
+
+    #[test]
+    fn test_status_from_result() {
+        assert_eq!(
+            TestStatus::from_result(" .....   Passed    "),
+            TestStatus::Passed
+        );
+        assert_eq!(TestStatus::from_result("   passed    "), TestStatus::Passed);
+        assert_eq!(
+            TestStatus::from_result(" ....***Failed    "),
+            TestStatus::Failed("".into())
+        );
+        assert_eq!(
+            TestStatus::from_result("***Failed  Required regular expression not found. "),
+            TestStatus::Failed("Required regular expression not found.".into())
+        );
+        assert_eq!(TestStatus::from_result("***Timeout "), TestStatus::Timeout);
+        assert_eq!(TestStatus::from_result("***Skipped "), TestStatus::Skipped);
+        assert_eq!(TestStatus::from_result("***Not Run "), TestStatus::NotRun);
+        assert_eq!(
+            TestStatus::from_result("***Exception: SegFault"),
+            TestStatus::Exception("SegFault".into())
+        );
+        assert_eq!(
+            TestStatus::from_result("***Something new "),
+            TestStatus::Failed("Something new".into())
+        );
+    }
+
+    #[test]
+    fn test_status_is_failure() {
+        assert!(!TestStatus::Passed.is_failure());
+        assert!(!TestStatus::Skipped.is_failure());
+        assert!(!TestStatus::NotRun.is_failure());
+        assert!(TestStatus::Failed("".into()).is_failure());
+        assert!(TestStatus::Timeout.is_failure());
+        assert!(TestStatus::Exception("SegFault".into()).is_failure());
+    }
+
+    #[test]
+    fn test_display_status() {
+        assert_eq!(TestStatus::Failed("".into()).to_string(), "Failed");
+        assert_eq!(
+            TestStatus::Exception("SegFault".into()).to_string(),
+            "Exception: SegFault"
+        );
+    }
 
     #[test]
     fn test_serialize_first_test() {
@@ -60,6 +394,7 @@ mod tests {
             start: Duration::from_millis(0),
             duration: Duration::from_millis(300),
             thread_number: 2,
+            ..Default::default()
         };
 
         assert_ser_tokens(
@@ -92,6 +427,7 @@ mod tests {
             start: Duration::from_millis(387),
             duration: Duration::from_millis(20),
             thread_number: 13,
+            ..Default::default()
         };
 
         assert_ser_tokens(
@@ -116,4 +452,180 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn test_serialize_multiple_processors() {
+        let trace = Trace {
+            name: "big".into(),
+            start: Duration::from_millis(1),
+            duration: Duration::from_millis(2),
+            thread_number: 3,
+            extra_threads: vec![0, 1],
+            ..Default::default()
+        };
+
+        assert_ser_tokens(
+            &trace,
+            &[
+                Token::Map { len: Some(9) },
+                Token::String("name"),
+                Token::String("big"),
+                Token::String("cat"),
+                Token::String("test"),
+                Token::String("ph"),
+                Token::String("X"),
+                Token::String("ts"),
+                Token::U64(1000),
+                Token::String("dur"),
+                Token::U64(2000),
+                Token::String("pid"),
+                Token::I32(0),
+                Token::String("tid"),
+                Token::U32(3),
+                Token::String("args"),
+                Token::Map { len: Some(1) },
+                Token::String("processors"),
+                Token::U64(3),
+                Token::MapEnd,
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_per_thread_copies() {
+        let trace = Trace {
+            name: "big".into(),
+            thread_number: 2,
+            extra_threads: vec![0],
+            ..Default::default()
+        };
+
+        let threads: Vec<u32> = trace.per_thread().iter().map(|t| t.thread_number).collect();
+        assert_eq!(threads, vec![2, 0]);
+    }
+
+    #[test]
+    fn test_serialize_failed_test() {
+        let trace = Trace {
+            name: "bad".into(),
+            status: TestStatus::Exception("SegFault".into()),
+            ..Default::default()
+        };
+
+        let expected = r#"{"name":"bad","cat":"test","ph":"X","ts":0,"dur":0,"pid":0,"tid":0,"args":{"status":"Exception: SegFault"}}"#;
+        assert_eq!(serde_json::to_string(&trace).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_serialize_outlier() {
+        let trace = Trace {
+            name: "slow".into(),
+            duration: Duration::from_secs(3),
+            outlier: Some(Outlier {
+                score: 12.5,
+                baseline_median: Duration::from_millis(1500),
+            }),
+            ..Default::default()
+        };
+
+        let expected = r#"{"name":"slow","cat":"test","ph":"X","ts":0,"dur":3000000,"pid":0,"tid":0,"cname":"terrible","args":{"outlier_score":12.5,"baseline_median_seconds":1.5}}"#;
+        assert_eq!(serde_json::to_string(&trace).unwrap(), expected);
+        assert_eq!(serde_json::from_str::<Trace>(expected).unwrap(), trace);
+    }
+
+    #[test]
+    fn test_deserialize_trace() {
+        let json = r#"{"name":"foo","cat":"test","ph":"X","ts":1500,"dur":200000,"pid":0,"tid":3}"#;
+        let trace: Trace = serde_json::from_str(json).unwrap();
+        let expected = Trace::new(
+            "foo",
+            Duration::from_micros(1500),
+            Duration::from_millis(200),
+        )
+        .with_thread_number(3);
+        assert_eq!(trace, expected);
+    }
+
+    #[test]
+    fn test_deserialize_fractional_microseconds() {
+        let json = r#"{"name":"foo","ph":"X","ts":1.5,"dur":2.25}"#;
+        let trace: Trace = serde_json::from_str(json).unwrap();
+        assert_eq!(trace.start, Duration::from_nanos(1500));
+        assert_eq!(trace.duration, Duration::from_nanos(2250));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let trace = Trace {
+            name: "bad".into(),
+            start: Duration::from_millis(387),
+            duration: Duration::from_millis(20),
+            thread_number: 1,
+            status: TestStatus::Failed("Required regular expression not found.".into()),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&trace).unwrap();
+        assert_eq!(serde_json::from_str::<Trace>(&json).unwrap(), trace);
+    }
+
+    #[test]
+    fn test_round_trip_drops_numbers_extra_threads_and_labels() {
+        let trace = Trace {
+            name: "big".into(),
+            number: Some(4),
+            thread_number: 2,
+            extra_threads: vec![0],
+            labels: vec!["slow".into()],
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&trace).unwrap();
+        let expected =
+            Trace::new("big", Duration::new(0, 0), Duration::new(0, 0)).with_thread_number(2);
+        assert_eq!(serde_json::from_str::<Trace>(&json).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_builder() {
+        let trace = Trace::new(
+            "a_test",
+            Duration::from_millis(5),
+            Duration::from_millis(20),
+        )
+        .with_number(3)
+        .with_thread_number(1)
+        .with_status(TestStatus::Timeout)
+        .with_labels(vec!["slow".into()]);
+
+        let expected = Trace {
+            name: "a_test".into(),
+            number: Some(3),
+            start: Duration::from_millis(5),
+            duration: Duration::from_millis(20),
+            thread_number: 1,
+            status: TestStatus::Timeout,
+            labels: vec!["slow".into()],
+            ..Default::default()
+        };
+        assert_eq!(trace, expected);
+    }
+
+    #[test]
+    fn test_deserialize_other_event() {
+        assert_de_tokens_error::<Trace>(
+            &[
+                Token::Map { len: Some(3) },
+                Token::String("name"),
+                Token::String("db"),
+                Token::String("ph"),
+                Token::String("s"),
+                Token::String("ts"),
+                Token::U64(1),
+                Token::MapEnd,
+            ],
+            "expected a complete event, \"ph\": \"X\", but got \"s\"",
+        );
+    }
 }
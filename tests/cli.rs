@@ -6,9 +6,14 @@
 // Used for testing the main cli interface of the application
 
 use assert_cmd::Command;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use predicates::prelude::*;
 use std::fs;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
 use tempfile::{tempdir, NamedTempFile};
 
 #[test]
@@ -138,9 +143,1138 @@ fn failure_on_partial_results() -> Result<(), Box<dyn std::error::Error>> {
             "#;
     let mut cmd = Command::cargo_bin("ctest_tracing")?;
 
+    cmd.arg("--strict").write_stdin(ctest_output);
+    let expected = r#"error: Saw end of "test_one" without start indicator
+ --> line 3
+  |
+3 |             1/2 Test #1: test_one ......................   Passed   0.20 sec
+  |
+"#;
+    cmd.assert().failure().stderr(expected);
+    Ok(())
+}
+
+#[test]
+fn lenient_on_partial_results() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  2: test_two
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+            2/2 Test #2: test_two ......................   Passed   0.20 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.write_stdin(ctest_output);
+    let expected = "{\"traceEvents\":[{\"name\":\"test_two\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}],\"otherData\":{\"diagnostics\":[{\"line\":3,\"text\":\"            1/2 Test #1: test_one ......................   Passed   0.20 sec\",\"message\":\"Saw end of \\\"test_one\\\" without start indicator\"}]}}";
+    cmd.assert()
+        .success()
+        .stdout(expected)
+        .stderr(predicate::str::contains(
+            "warning: line 3: Saw end of \"test_one\" without start indicator",
+        ));
+    Ok(())
+}
+
+#[test]
+fn drawing_fixture_arrows_from_test_info() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: setup
+            1/2 Test #1: setup ......................   Passed   0.20 sec
+                Start  2: user
+            2/2 Test #2: user ......................   Passed   0.30 sec
+            "#;
+    let json_v1 = r#"{"kind": "ctestInfo", "tests": [
+            {"name": "setup", "properties": [{"name": "FIXTURES_SETUP", "value": ["db"]}]},
+            {"name": "user", "properties": [{"name": "FIXTURES_REQUIRED", "value": ["db"]}]}
+        ]}"#;
+    let mut info = NamedTempFile::new()?;
+    info.write_all(json_v1.as_bytes())?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--test-info")
+        .arg(info.path())
+        .write_stdin(ctest_output);
+    let expected = "[{\"name\":\"setup\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0},{\"name\":\"user\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":200000,\"dur\":300000,\"pid\":0,\"tid\":0},{\"name\":\"db\",\"cat\":\"dependency\",\"ph\":\"s\",\"id\":1,\"ts\":199999,\"pid\":0,\"tid\":0},{\"name\":\"db\",\"cat\":\"dependency\",\"ph\":\"f\",\"id\":1,\"ts\":200000,\"pid\":0,\"tid\":0,\"bp\":\"e\"}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn multiple_processors_from_command_line() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/1 Test #1: test_one ......................   Passed   0.20 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--processors")
+        .arg("test_one=2")
+        .write_stdin(ctest_output);
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0,\"args\":{\"processors\":2}},{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":1,\"args\":{\"processors\":2}}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn invalid_processors_mapping() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--processors").arg("test_one").write_stdin("");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Expected NAME=COUNT"));
+    Ok(())
+}
+
+#[test]
+fn resource_slot_tracks_from_spec_file() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: gpu_test
+            1: Environment variables:
+            1:  CTEST_RESOURCE_GROUP_COUNT=1
+            1:  CTEST_RESOURCE_GROUP_0=gpus
+            1:  CTEST_RESOURCE_GROUP_0_GPUS=id:0,slots:1
+            1/1 Test #1: gpu_test ......................   Passed   0.20 sec
+            "#;
+    let spec = r#"{"version": {"major": 1, "minor": 0}, "local": [{"gpus": [{"id": "0"}]}]}"#;
+    let mut spec_file = NamedTempFile::new()?;
+    spec_file.write_all(spec.as_bytes())?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--resource-spec-file")
+        .arg(spec_file.path())
+        .write_stdin(ctest_output);
+    let expected = "[{\"name\":\"gpu_test\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0},{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":1,\"args\":{\"name\":\"resources\"}},{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":0,\"args\":{\"name\":\"gpus 0 slot 0\"}},{\"name\":\"gpu_test\",\"cat\":\"resource\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":1,\"tid\":0}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn strip_custom_prefix() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = "[job 3]     Start  1: test_one\r\n\
+        [job 3] 1/1 Test #1: test_one ......................   Passed   0.20 sec\r\n";
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--strip-prefix")
+        .arg(r"^\[job \d+\]")
+        .write_stdin(ctest_output);
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn ignoring_invalid_utf8_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut ctest_output = b"    Start  1: test_one\n\xff\xfe garbage \x80\n".to_vec();
+    ctest_output.extend_from_slice(b"1/1 Test #1: test_one ........   Passed   0.20 sec\n");
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
     cmd.write_stdin(ctest_output);
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn reading_gzip_compressed_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/1 Test #1: test_one ......................   Passed   0.20 sec
+            "#;
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(ctest_output.as_bytes())?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.write_stdin(encoder.finish()?);
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn writing_gzip_output_file() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/1 Test #1: test_one ......................   Passed   0.20 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    let dir = tempdir()?;
+    let file_path = dir.path().join("trace.json.gz");
+    cmd.arg("-o")
+        .arg(file_path.as_os_str())
+        .write_stdin(ctest_output);
+    cmd.assert().success().stdout("");
+
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}]";
+    let mut contents = String::new();
+    GzDecoder::new(fs::File::open(file_path)?).read_to_string(&mut contents)?;
+    assert_eq!(expected, contents);
+    Ok(())
+}
+
+#[test]
+fn following_a_growing_log() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let log_path = dir.path().join("ctest.log");
+    let trace_path = dir.path().join("trace.json");
+    let mut log = fs::File::create(&log_path)?;
+    log.write_all(
+        br#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+"#,
+    )?;
+    log.flush()?;
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("ctest_tracing"))
+        .arg("--follow")
+        .arg(&log_path)
+        .arg("-o")
+        .arg(&trace_path)
+        .spawn()?;
+
+    // The first test shows up while ctest is still running
+    let first = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}";
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while fs::read_to_string(&trace_path).unwrap_or_default() != first {
+        assert!(Instant::now() < deadline, "first test never written");
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert!(child.try_wait()?.is_none());
+
+    log.write_all(
+        br#"            2/2 Test #2: test_two ......................   Passed   0.30 sec
+
+100% tests passed, 0 tests failed out of 2
+
+Total Test time (real) =   0.52 sec
+"#,
+    )?;
+    log.flush()?;
+
+    while child.try_wait()?.is_none() {
+        assert!(Instant::now() < deadline, "follow never finished");
+        thread::sleep(Duration::from_millis(50));
+    }
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0},{\"name\":\"test_two\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":200000,\"dur\":300000,\"pid\":0,\"tid\":0}]";
+    assert_eq!(fs::read_to_string(&trace_path)?, expected);
+    Ok(())
+}
+
+#[test]
+fn following_a_rewritten_log() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let log_path = dir.path().join("ctest.log");
+    let trace_path = dir.path().join("trace.json");
+    fs::write(
+        &log_path,
+        r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+            2: Output from test_two, which makes this log longer than the next one
+"#,
+    )?;
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("ctest_tracing"))
+        .arg("--follow")
+        .arg(&log_path)
+        .arg("-o")
+        .arg(&trace_path)
+        .spawn()?;
+
+    let first = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}";
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while fs::read_to_string(&trace_path).unwrap_or_default() != first {
+        assert!(Instant::now() < deadline, "first test never written");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    // ctest is run again, truncating the log, so the trace starts over
+    fs::write(
+        &log_path,
+        r#"
+                Start  1: test_three
+            1/1 Test #1: test_three ....................   Passed   0.10 sec
+
+100% tests passed, 0 tests failed out of 1
+
+Total Test time (real) =   0.11 sec
+"#,
+    )?;
+
+    while child.try_wait()?.is_none() {
+        assert!(Instant::now() < deadline, "follow never finished");
+        thread::sleep(Duration::from_millis(50));
+    }
+    let expected = "[{\"name\":\"test_three\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":100000,\"pid\":0,\"tid\":0}]";
+    assert_eq!(fs::read_to_string(&trace_path)?, expected);
+    Ok(())
+}
+
+#[test]
+fn following_a_rewritten_log_to_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let log_path = dir.path().join("ctest.log");
+    fs::write(
+        &log_path,
+        r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+            2: Output from test_two, which makes this log longer than the next one
+"#,
+    )?;
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("ctest_tracing"))
+        .arg("--follow")
+        .arg(&log_path)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+
+    let first = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}";
+    let mut stdout = child.stdout.take().unwrap();
+    let mut written = vec![0; first.len()];
+    stdout.read_exact(&mut written)?;
+    assert_eq!(String::from_utf8(written)?, first);
+
+    // stdout can't be emptied, so the trace is closed instead of starting
+    // over
+    fs::write(
+        &log_path,
+        r#"
+                Start  1: test_three
+"#,
+    )?;
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while child.try_wait()?.is_none() {
+        assert!(Instant::now() < deadline, "follow never finished");
+        thread::sleep(Duration::from_millis(50));
+    }
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest)?;
+    assert_eq!(rest, "]");
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "warning: The log was rewritten, following stopped as the output can't be emptied\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn following_with_diagnostics() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let log_path = dir.path().join("ctest.log");
+    let trace_path = dir.path().join("trace.json");
+    fs::write(
+        &log_path,
+        r#"
+                Start  2: test_two
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+            2/2 Test #2: test_two ......................   Passed   0.20 sec
+
+Total Test time (real) =   0.41 sec
+"#,
+    )?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--follow")
+        .arg(&log_path)
+        .arg("-o")
+        .arg(&trace_path)
+        .timeout(Duration::from_secs(30));
+    cmd.assert().success().stderr(predicate::str::contains(
+        "warning: line 3: Saw end of \"test_one\" without start indicator",
+    ));
+    // The same as without --follow
+    let expected = "{\"traceEvents\":[{\"name\":\"test_two\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}],\"otherData\":{\"diagnostics\":[{\"line\":3,\"text\":\"            1/2 Test #1: test_one ......................   Passed   0.20 sec\",\"message\":\"Saw end of \\\"test_one\\\" without start indicator\"}]}}";
+    assert_eq!(fs::read_to_string(&trace_path)?, expected);
+    Ok(())
+}
+
+#[test]
+fn following_until_idle() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let log_path = dir.path().join("ctest.log");
+    fs::write(
+        &log_path,
+        r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+"#,
+    )?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--follow")
+        .arg(&log_path)
+        .arg("--idle-timeout")
+        .arg("0.5")
+        .timeout(Duration::from_secs(30));
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn follow_requires_input_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--follow").write_stdin("");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--follow requires an input file"));
+    Ok(())
+}
+
+#[test]
+fn follow_rejects_compressed_input() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let log_path = dir.path().join("ctest.log.gz");
+    let mut encoder = GzEncoder::new(fs::File::create(&log_path)?, Compression::default());
+    encoder.write_all(b"    Start  1: test_one\n")?;
+    encoder.finish()?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--follow").arg(&log_path);
+    cmd.assert()
+        .failure()
+        .stderr("error: --follow can't read compressed input\n");
+    Ok(())
+}
+
+#[test]
+fn dashboard_requires_terminal() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--dashboard").write_stdin("");
     cmd.assert().failure().stderr(predicate::str::contains(
-        "Saw end of \\\"test_one\\\" without start indicator",
+        "The dashboard requires stderr to be a terminal",
+    ));
+    Ok(())
+}
+
+#[test]
+fn failed_test_status_in_args() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/1 Test #1: test_one ......................***Timeout   1.50 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.write_stdin(ctest_output);
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":1500000,\"pid\":0,\"tid\":0,\"args\":{\"status\":\"Timeout\"}}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn reading_chrome_trace() -> Result<(), Box<dyn std::error::Error>> {
+    let trace = r#"{"traceEvents":[
+        {"name":"test_two","ph":"B","ts":200000,"pid":0,"tid":0},
+        {"name":"test_one","cat":"test","ph":"X","ts":0,"dur":200000,"pid":0,"tid":0},
+        {"ph":"E","ts":500000,"pid":0,"tid":0}
+    ]}"#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.write_stdin(trace);
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0},{\"name\":\"test_two\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":200000,\"dur\":300000,\"pid\":0,\"tid\":0}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn reading_ctest_output_starting_with_bracket() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"[ctest]     Start  1: test_one
+[ctest] 1/1 Test #1: test_one ......................   Passed   0.20 sec
+"#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.write_stdin(ctest_output);
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn reading_invalid_chrome_trace() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--from")
+        .arg("chrome")
+        .write_stdin("Test project /tmp");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::starts_with("error: "));
+    Ok(())
+}
+
+#[test]
+fn sharding_tests() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/4 Test #1: test_one ......................   Passed   0.50 sec
+                Start  2: test.two
+            2/4 Test #2: test.two ......................   Passed   0.40 sec
+                Start  3: test_three
+            3/4 Test #3: test_three ....................   Passed   0.30 sec
+                Start  4: test_four
+            4/4 Test #4: test_four .....................   Passed   0.20 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("shard")
+        .arg("--shards")
+        .arg("2")
+        .write_stdin(ctest_output);
+    let expected = "-R '^(test_one|test_four)$'\n-R '^(test\\.two|test_three)$'\n";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn sharding_tests_to_json() -> Result<(), Box<dyn std::error::Error>> {
+    let trace = r#"[
+        {"name":"test_one","cat":"test","ph":"X","ts":0,"dur":500000,"pid":0,"tid":0},
+        {"name":"test_two","cat":"test","ph":"X","ts":0,"dur":250000,"pid":0,"tid":1},
+        {"name":"test_three","cat":"test","ph":"X","ts":250000,"dur":250000,"pid":0,"tid":1}
+    ]"#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("shard")
+        .arg("--shards")
+        .arg("1")
+        .arg("-j")
+        .arg("2")
+        .arg("--format")
+        .arg("json")
+        .write_stdin(trace);
+    let expected =
+        "[{\"tests\":[\"test_one\",\"test_three\",\"test_two\"],\"estimated_seconds\":0.5}]\n";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn sharding_tests_to_cmake() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.50 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................   Passed   0.40 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--from")
+        .arg("ctest")
+        .arg("shard")
+        .arg("--shards=2")
+        .arg("--format=cmake")
+        .write_stdin(ctest_output);
+    let expected = "set(CTEST_SHARD_0 \"test_one\")\nset(CTEST_SHARD_1 \"test_two\")\n";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn sharding_requires_a_shard() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("shard").arg("--shards").arg("0").write_stdin("");
+    cmd.assert()
+        .failure()
+        .stderr("error: --shards must be at least 1\n");
+    Ok(())
+}
+
+#[test]
+fn sharding_more_shards_than_tests() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.50 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................   Passed   0.40 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("shard")
+        .arg("--shards")
+        .arg("3")
+        .write_stdin(ctest_output);
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr("error: --shards 3 is more than the 2 tests in the input\n");
+    Ok(())
+}
+
+#[test]
+fn finding_flaky_tests() -> Result<(), Box<dyn std::error::Error>> {
+    let passing = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................   Passed   0.30 sec
+            "#;
+    let failing = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................***Failed   0.40 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................   Passed   0.30 sec
+            "#;
+    let dir = tempdir()?;
+    let mut logs = vec![];
+    for (index, log) in [passing, failing, passing].iter().enumerate() {
+        let path = dir.path().join(format!("{}.log", index));
+        fs::write(&path, log)?;
+        logs.push(path);
+    }
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("flaky").args(&logs).arg("--format").arg("json");
+    let expected = format!(
+        "[{{\"name\":\"test_one\",\"runs\":3,\"flips\":2,\"flip_rate\":1.0,\"mean_seconds\":0.266666667,\"duration_variance\":0.008888888888888889,\"failed_runs\":[\"{}\"]}}]\n",
+        logs[1].display()
+    );
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn finding_no_flaky_tests() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/1 Test #1: test_one ......................   Passed   0.20 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("flaky").arg("-").write_stdin(ctest_output);
+    cmd.assert().success().stdout("No flaky tests in 1 runs\n");
+    Ok(())
+}
+
+#[test]
+fn recording_timing_history() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let store = dir.path().join("history.jsonl");
+    for (label, seconds) in [("first", "0.20"), ("second", "0.40"), ("third", "0.30")] {
+        let ctest_output = format!(
+            "    Start  1: test_one\n1/1 Test #1: test_one ....   Passed   {} sec\n",
+            seconds
+        );
+        let mut cmd = Command::cargo_bin("ctest_tracing")?;
+        cmd.arg("record")
+            .arg("--store")
+            .arg(&store)
+            .arg("--label")
+            .arg(label)
+            .write_stdin(ctest_output);
+        cmd.assert().success().stdout("");
+    }
+
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+    cmd.arg("history")
+        .arg("test_one")
+        .arg("--store")
+        .arg(&store)
+        .arg("--last")
+        .arg("2")
+        .arg("--format")
+        .arg("json");
+    let expected = "{\"trend\":{\"name\":\"test_one\",\"runs\":2,\"median_seconds\":0.35,\"p90_seconds\":0.4,\"trend\":-0.2857142857142858},\"runs\":[{\"run\":2,\"label\":\"second\",\"seconds\":0.4,\"status\":\"Passed\"},{\"run\":3,\"label\":\"third\",\"seconds\":0.3,\"status\":\"Passed\"}]}\n";
+    cmd.assert().success().stdout(expected);
+
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+    cmd.arg("trends").arg("--store").arg(&store);
+    let expected = "      Median          P90    Trend  Runs  Test\n   0.300 sec    0.400 sec   +16.7%     3  test_one\n";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn trends_without_history() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("trends")
+        .arg("--store")
+        .arg(dir.path().join("missing.jsonl"));
+    cmd.assert().failure().stderr(predicate::str::starts_with(
+        "error: Failed to open the timing history",
     ));
     Ok(())
 }
+
+#[test]
+fn marking_outliers_against_baseline() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+    for (index, seconds) in ["0.90", "1.00", "1.10"].iter().enumerate() {
+        let path = dir.path().join(format!("{}.log", index));
+        fs::write(
+            &path,
+            format!(
+                "    Start  1: test_one\n1/1 Test #1: test_one ....   Passed   {} sec\n",
+                seconds
+            ),
+        )?;
+        cmd.arg("--baseline").arg(path);
+    }
+
+    cmd.write_stdin("    Start  1: test_one\n1/1 Test #1: test_one ....   Passed   2.00 sec\n");
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":2000000,\"pid\":0,\"tid\":0,\"cname\":\"terrible\",\"args\":{\"outlier_score\":6.744999999999999,\"baseline_median_seconds\":1.0}}]";
+    cmd.assert()
+        .success()
+        .stdout(expected)
+        .stderr("warning: \"test_one\" took 2.000 sec, its baseline median is 1.000 sec (outlier score 6.7)\n");
+    Ok(())
+}
+
+#[test]
+fn reporting_outliers_against_history() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let store = dir.path().join("history.jsonl");
+    let mut history = String::new();
+    for seconds in ["0.5", "0.5", "0.5"] {
+        history.push_str(&format!(
+            "{{\"recorded_at\":0,\"source\":\"-\",\"tests\":[{{\"name\":\"test_one\",\"seconds\":{},\"status\":\"Passed\"}},{{\"name\":\"test_two\",\"seconds\":1.0,\"status\":\"Passed\"}}]}}\n",
+            seconds
+        ));
+    }
+    fs::write(&store, history)?;
+    let ctest_output = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.50 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................   Passed   1.50 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("outliers")
+        .arg("--baseline-history")
+        .arg(&store)
+        .arg("--format")
+        .arg("json")
+        .write_stdin(ctest_output);
+    let expected = "[{\"name\":\"test_two\",\"seconds\":1.5,\"baseline_median_seconds\":1.0,\"score\":33.725}]\n";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn writing_junit() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"2021-11-20T10:00:00.5000000Z       Start  1: test_one
+2021-11-20T10:00:00.7000000Z 1/2 Test #1: test_one ......................   Passed   0.20 sec
+2021-11-20T10:00:00.7000000Z       Start  2: test_two
+2021-11-20T10:00:01.0000000Z 2/2 Test #2: test_two ......................***Timeout   0.30 sec
+"#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format").arg("junit").write_stdin(ctest_output);
+    let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="ctest" tests="2" failures="1" errors="0" skipped="0" time="0.500" timestamp="2021-11-20T10:00:00">
+  <testcase name="test_one" classname="test_one" time="0.200"/>
+  <testcase name="test_two" classname="test_two" time="0.300">
+    <failure message="Timeout" type="Timeout"/>
+  </testcase>
+</testsuite>
+"#;
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn writing_github_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................***Failed  Required regular expression not found.   0.30 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format")
+        .arg("github")
+        .arg("--slowest")
+        .arg("1")
+        .write_stdin(ctest_output);
+    let expected = r#"## ctest Results
+
+| Passed | Failed | Skipped | Wall Time |
+| ---: | ---: | ---: | ---: |
+| 1 | 1 | 0 | 0.50 sec |
+
+### Failed Tests
+
+| Test | Status | Duration |
+| --- | --- | ---: |
+| test_two | Failed  Required regular expression not found. | 0.30 sec |
+
+### Slowest Tests
+
+| Test | Duration |
+| --- | ---: |
+| test_two | 0.30 sec |
+"#;
+    cmd.assert().success().stdout(expected).stderr(
+        "::error title=test_two::Failed  Required regular expression not found. after 0.30 sec\n",
+    );
+    Ok(())
+}
+
+#[test]
+fn writing_teamcity_messages() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................***Failed  Required regular expression not found.   0.30 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format")
+        .arg("teamcity")
+        .write_stdin(ctest_output);
+    let expected = r#"##teamcity[testStarted name='test_one' flowId='0']
+##teamcity[testFinished name='test_one' duration='200' flowId='0']
+##teamcity[testStarted name='test_two' flowId='0']
+##teamcity[testFailed name='test_two' message='Required regular expression not found.' flowId='0']
+##teamcity[testFinished name='test_two' duration='300' flowId='0']
+"#;
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn writing_tap() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................***Failed  Required regular expression not found.   0.30 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format").arg("tap").write_stdin(ctest_output);
+    let expected = r#"TAP version 14
+1..2
+ok 1 - test_one
+  ---
+  duration_ms: 200
+  start_ms: 0
+  lane: 0
+  status: "Passed"
+  ...
+not ok 2 - test_two
+  ---
+  duration_ms: 300
+  start_ms: 200
+  lane: 0
+  status: "Failed  Required regular expression not found."
+  ...
+"#;
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn reading_tap() -> Result<(), Box<dyn std::error::Error>> {
+    let tap = r#"TAP version 14
+1..2
+ok 1 - test_one
+  ---
+  duration_ms: 200
+  ...
+not ok 2 - test_two
+  ---
+  duration_ms: 300
+  message: "expected 1"
+  ...
+"#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.write_stdin(tap);
+    let expected = "[{\"name\":\"test_one\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":0,\"dur\":200000,\"pid\":0,\"tid\":0},{\"name\":\"test_two\",\"cat\":\"test\",\"ph\":\"X\",\"ts\":200000,\"dur\":300000,\"pid\":0,\"tid\":0,\"args\":{\"status\":\"Failed  expected 1\"}}]";
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn writing_csv_with_labels() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................***Timeout   1.50 sec
+            "#;
+    let json_v1 = r#"{"kind": "ctestInfo", "tests": [
+            {"name": "test_two", "properties": [{"name": "LABELS", "value": ["db", "slow"]}]}
+        ]}"#;
+    let mut info = NamedTempFile::new()?;
+    info.write_all(json_v1.as_bytes())?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format")
+        .arg("csv")
+        .arg("--test-info")
+        .arg(info.path())
+        .arg("--run-id")
+        .arg("1234")
+        .write_stdin(ctest_output);
+    let expected = r#"name,number,start_seconds,duration_seconds,lane,status,labels,run_id
+test_one,1,0.0,0.2,0,Passed,,1234
+test_two,2,0.2,1.5,0,Timeout,db;slow,1234
+"#;
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn writing_jsonl_columns() -> Result<(), Box<dyn std::error::Error>> {
+    let ctest_output = r#"
+                Start  1: test_one
+            1/2 Test #1: test_one ......................   Passed   0.20 sec
+                Start  2: test_two
+            2/2 Test #2: test_two ......................***Timeout   1.50 sec
+            "#;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format")
+        .arg("jsonl")
+        .arg("--columns")
+        .arg("status,name,duration_seconds")
+        .write_stdin(ctest_output);
+    let expected = r#"{"name":"test_one","duration_seconds":0.2,"status":"Passed"}
+{"name":"test_two","duration_seconds":1.5,"status":"Timeout"}
+"#;
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+const TIMESTAMPED_OUTPUT: &str = r#"2021-11-20T10:00:00.5000000Z       Start  1: test_one
+2021-11-20T10:00:00.7000000Z 1/2 Test #1: test_one ......................   Passed   0.20 sec
+2021-11-20T10:00:00.7000000Z       Start  2: test_two
+2021-11-20T10:00:01.0000000Z 2/2 Test #2: test_two ......................***Timeout   0.30 sec
+"#;
+
+// The spans of `TIMESTAMPED_OUTPUT`, with the random trace ID taken out
+fn expected_spans() -> serde_json::Value {
+    serde_json::json!([
+        {
+            "spanId": "0000000000000001",
+            "name": "ctest",
+            "kind": 1,
+            "startTimeUnixNano": "1637402400500000000",
+            "endTimeUnixNano": "1637402401000000000",
+            "attributes": [
+                {"key": "test.suite.name", "value": {"stringValue": "ctest"}},
+                {"key": "test.suite.run.status", "value": {"stringValue": "failure"}},
+                {"key": "ctest.tests", "value": {"intValue": "2"}},
+                {"key": "ctest.failed", "value": {"intValue": "1"}},
+                {"key": "ctest.run_id", "value": {"stringValue": "1234"}}
+            ],
+            "status": {"code": 2, "message": "1 test failed"}
+        },
+        {
+            "spanId": "0000000000000002",
+            "parentSpanId": "0000000000000001",
+            "name": "test_one",
+            "kind": 1,
+            "startTimeUnixNano": "1637402400500000000",
+            "endTimeUnixNano": "1637402400700000000",
+            "attributes": [
+                {"key": "test.case.name", "value": {"stringValue": "test_one"}},
+                {"key": "test.case.result.status", "value": {"stringValue": "pass"}},
+                {"key": "ctest.status", "value": {"stringValue": "Passed"}},
+                {"key": "ctest.lane", "value": {"intValue": "0"}},
+                {"key": "ctest.test.number", "value": {"intValue": "1"}}
+            ],
+            "status": {"code": 1}
+        },
+        {
+            "spanId": "0000000000000003",
+            "parentSpanId": "0000000000000001",
+            "name": "test_two",
+            "kind": 1,
+            "startTimeUnixNano": "1637402400700000000",
+            "endTimeUnixNano": "1637402401000000000",
+            "attributes": [
+                {"key": "test.case.name", "value": {"stringValue": "test_two"}},
+                {"key": "test.case.result.status", "value": {"stringValue": "fail"}},
+                {"key": "ctest.status", "value": {"stringValue": "Timeout"}},
+                {"key": "ctest.lane", "value": {"intValue": "0"}},
+                {"key": "ctest.test.number", "value": {"intValue": "2"}}
+            ],
+            "status": {"code": 2, "message": "Timeout"}
+        }
+    ])
+}
+
+// The spans of an OTLP/JSON request, checking they share a trace ID and
+// taking it out
+fn spans_of(request: &[u8]) -> serde_json::Value {
+    let json: serde_json::Value = serde_json::from_slice(request).unwrap();
+    let resource = &json["resourceSpans"][0];
+    assert_eq!(
+        resource["resource"]["attributes"][0]["value"]["stringValue"],
+        "ci"
+    );
+    let mut spans = resource["scopeSpans"][0]["spans"].clone();
+    let trace_id = spans[0]["traceId"].as_str().unwrap().to_string();
+    assert_eq!(trace_id.len(), 32);
+    for span in spans.as_array_mut().unwrap() {
+        let span = span.as_object_mut().unwrap();
+        assert_eq!(span.remove("traceId").unwrap(), trace_id.as_str());
+    }
+    spans
+}
+
+#[test]
+fn writing_otlp_json() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format")
+        .arg("otlp")
+        .arg("--otlp-service-name")
+        .arg("ci")
+        .arg("--run-id")
+        .arg("1234")
+        .write_stdin(TIMESTAMPED_OUTPUT);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    assert_eq!(spans_of(&output), expected_spans());
+    Ok(())
+}
+
+// A collector which accepts one request, replying with `status`, and hands
+// back the request's head and body
+fn mock_collector(status: &'static str) -> (String, thread::JoinHandle<(String, Vec<u8>)>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+    let collector = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = std::io::BufReader::new(stream);
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            std::io::BufRead::read_line(&mut reader, &mut head).unwrap();
+        }
+        let length: usize = head
+            .to_lowercase()
+            .lines()
+            .find_map(|l| l.strip_prefix("content-length:").map(|v| v.trim().parse()))
+            .unwrap()
+            .unwrap();
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        let response = format!("HTTP/1.1 {}\r\nContent-Length: 2\r\n\r\n{{}}", status);
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        (head, body)
+    });
+    (endpoint, collector)
+}
+
+#[test]
+fn sending_to_otlp_collector() -> Result<(), Box<dyn std::error::Error>> {
+    let (endpoint, collector) = mock_collector("200 OK");
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format")
+        .arg("csv")
+        .arg("--columns")
+        .arg("name")
+        .arg("--otlp-endpoint")
+        .arg(&endpoint)
+        .arg("--otlp-header")
+        .arg("x-api-key=secret")
+        .arg("--otlp-service-name")
+        .arg("ci")
+        .arg("--run-id")
+        .arg("1234")
+        .write_stdin(TIMESTAMPED_OUTPUT);
+    cmd.assert().success().stdout("name\ntest_one\ntest_two\n");
+
+    let (head, body) = collector.join().unwrap();
+    assert!(head.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+    let head = head.to_lowercase();
+    assert!(head.contains("\r\ncontent-type: application/json\r\n"));
+    assert!(head.contains("\r\nx-api-key: secret\r\n"));
+    assert_eq!(spans_of(&body), expected_spans());
+    Ok(())
+}
+
+#[test]
+fn otlp_collector_unreachable() -> Result<(), Box<dyn std::error::Error>> {
+    // Nothing listens on the port once the listener is dropped
+    let address = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format")
+        .arg("csv")
+        .arg("--columns")
+        .arg("name")
+        .arg("--otlp-endpoint")
+        .arg(format!("http://{}/v1/traces", address))
+        .write_stdin(TIMESTAMPED_OUTPUT);
+    cmd.assert()
+        .failure()
+        .stdout("name\ntest_one\ntest_two\n")
+        .stderr(predicate::str::starts_with(format!(
+            "error: The OTLP collector at http://{}/v1/traces didn't accept the spans: ",
+            address
+        )));
+    Ok(())
+}
+
+#[test]
+fn otlp_collector_rejects_spans() -> Result<(), Box<dyn std::error::Error>> {
+    let (endpoint, collector) = mock_collector("400 Bad Request");
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format")
+        .arg("csv")
+        .arg("--columns")
+        .arg("name")
+        .arg("--otlp-endpoint")
+        .arg(&endpoint)
+        .write_stdin(TIMESTAMPED_OUTPUT);
+    cmd.assert()
+        .failure()
+        .stdout("name\ntest_one\ntest_two\n")
+        .stderr(format!(
+            "error: The OTLP collector at {} didn't accept the spans: status 400 {{}}\n",
+            endpoint
+        ));
+    collector.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn otlp_collector_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    // The connection is queued, but the request is never read or replied to
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let endpoint = format!("http://{}/v1/traces", listener.local_addr()?);
+    let mut cmd = Command::cargo_bin("ctest_tracing")?;
+
+    cmd.arg("--format")
+        .arg("csv")
+        .arg("--columns")
+        .arg("name")
+        .arg("--otlp-endpoint")
+        .arg(&endpoint)
+        .arg("--otlp-timeout")
+        .arg("0.2")
+        .write_stdin(TIMESTAMPED_OUTPUT);
+    cmd.assert()
+        .failure()
+        .stdout("name\ntest_one\ntest_two\n")
+        .stderr(predicate::str::starts_with(format!(
+            "error: The OTLP collector at {} didn't accept the spans: ",
+            endpoint
+        )));
+    drop(listener);
+    Ok(())
+}